@@ -0,0 +1,331 @@
+use crate::interest::Period;
+use crate::timeline::PointInTime;
+use crate::transaction::ClientId;
+use anyhow::{anyhow, Result};
+use std::path::PathBuf;
+
+/// What application should do
+#[derive(Debug, Default, PartialEq, Eq)]
+pub enum Command {
+    /// Processes transactions, outputting final clients state
+    #[default]
+    Process,
+    /// Outputs chronological statement of single client
+    Statement { cid: ClientId },
+    /// Outputs single client state at given point of processing
+    Balance { cid: ClientId, point: PointInTime },
+}
+
+/// How log events are written to stderr
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable lines
+    #[default]
+    Text,
+    /// Single JSON object per event, for log pipelines
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(anyhow!("Unknown log format: {}, expected text or json", s)),
+        }
+    }
+}
+
+/// Command line arguments
+///
+/// Parsing is done by hand - there are just few options, and pulling argument parsing crate for
+/// them is not worth it.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Args {
+    /// Command to perform
+    pub command: Command,
+    /// Input csv file
+    pub input: PathBuf,
+    /// Configuration file, defaults are used if not given
+    pub config: Option<PathBuf>,
+    /// Currency conversion rates file, nothing can be converted if not given
+    pub fx_rates: Option<PathBuf>,
+    /// File to store disputes stranded on locked accounts in
+    pub stranded_report: Option<PathBuf>,
+    /// File to store ledger trial balance in
+    pub trial_balance: Option<PathBuf>,
+    /// File to store invariant violations in
+    pub invariants_report: Option<PathBuf>,
+    /// File to store metrics in, in Prometheus text format
+    pub metrics: Option<PathBuf>,
+    /// File to store clients risk report in
+    pub risk_report: Option<PathBuf>,
+    /// Period to accrue interest for after processing
+    pub accrue_interest: Option<Period>,
+    /// Format of log events
+    pub log_format: LogFormat,
+    /// Name of input schema from config, the default one is used if not given
+    pub input_schema: Option<String>,
+    /// Tenant which client is queried for, only for statement and balance
+    pub tenant: Option<String>,
+}
+
+impl Args {
+    /// Parses arguments from the process command line
+    pub fn from_env() -> Result<Self> {
+        // Skipping app name
+        Self::parse(std::env::args().skip(1))
+    }
+
+    /// Parses arguments, without app name
+    ///
+    /// Command is given as the first argument, defaulting to processing transactions:
+    /// `[statement --client <id> | balance --client <id> (--after-tx <tx> | --at <timestamp>)]
+    /// [options] <input>`. Statement and balance take client of `--tenant <name>`, if given.
+    /// Interest accrual (`--accrue-interest <from>:<to>`) is done only when
+    /// processing transactions.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut args = args.into_iter().peekable();
+        let command = args
+            .next_if(|arg| arg == "statement" || arg == "balance")
+            .unwrap_or_default();
+        let mut cid = None;
+        let mut point = None;
+        let mut input = None;
+        let mut config = None;
+        let mut fx_rates = None;
+        let mut stranded_report = None;
+        let mut trial_balance = None;
+        let mut invariants_report = None;
+        let mut metrics = None;
+        let mut risk_report = None;
+        let mut accrue_interest = None;
+        let mut log_format = LogFormat::default();
+        let mut input_schema = None;
+        let mut tenant = None;
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| anyhow!("Missing value for {}", name))
+            };
+
+            match arg.as_str() {
+                "--config" => config = Some(value("--config")?.into()),
+                "--fx-rates" => fx_rates = Some(value("--fx-rates")?.into()),
+                "--stranded-report" => stranded_report = Some(value("--stranded-report")?.into()),
+                "--trial-balance" => trial_balance = Some(value("--trial-balance")?.into()),
+                "--invariants-report" => {
+                    invariants_report = Some(value("--invariants-report")?.into())
+                }
+                "--metrics" => metrics = Some(value("--metrics")?.into()),
+                "--risk-report" => risk_report = Some(value("--risk-report")?.into()),
+                "--log-format" => log_format = value("--log-format")?.parse()?,
+                "--input-schema" => input_schema = Some(value("--input-schema")?),
+                "--accrue-interest" if command.is_empty() => {
+                    accrue_interest = Some(value("--accrue-interest")?.parse()?)
+                }
+                "--client" if !command.is_empty() => cid = Some(value("--client")?.parse()?),
+                "--tenant" if !command.is_empty() => tenant = Some(value("--tenant")?),
+                "--after-tx" if command == "balance" => {
+                    point = Some(PointInTime::AfterTx(value("--after-tx")?.parse()?))
+                }
+                "--at" if command == "balance" => {
+                    point = Some(PointInTime::At(value("--at")?.parse()?))
+                }
+                arg if arg.starts_with("--") => return Err(anyhow!("Unknown option: {}", arg)),
+                _ if input.is_some() => return Err(anyhow!("Unexpected argument: {}", arg)),
+                _ => input = Some(PathBuf::from(arg)),
+            }
+        }
+
+        let command = match command.as_str() {
+            "statement" => Command::Statement {
+                cid: cid.ok_or_else(|| anyhow!("Missing --client for statement"))?,
+            },
+            "balance" => Command::Balance {
+                cid: cid.ok_or_else(|| anyhow!("Missing --client for balance"))?,
+                point: point.ok_or_else(|| anyhow!("Missing --after-tx or --at for balance"))?,
+            },
+            _ => Command::Process,
+        };
+
+        Ok(Self {
+            command,
+            input: input.ok_or_else(|| anyhow!("Missing input file"))?,
+            config,
+            fx_rates,
+            stranded_report,
+            trial_balance,
+            invariants_report,
+            metrics,
+            risk_report,
+            accrue_interest,
+            log_format,
+            input_schema,
+            tenant,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Args, Command, LogFormat};
+    use crate::interest::Period;
+    use crate::timeline::PointInTime;
+    use std::path::PathBuf;
+
+    fn args(args: &[&str]) -> anyhow::Result<Args> {
+        Args::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            args(&["input.csv"]).unwrap(),
+            Args {
+                input: PathBuf::from("input.csv"),
+                ..Args::default()
+            }
+        );
+
+        assert_eq!(
+            args(&[
+                "--config",
+                "config.toml",
+                "input.csv",
+                "--stranded-report",
+                "stranded.csv",
+                "--trial-balance",
+                "trial.csv",
+                "--risk-report",
+                "risk.csv",
+                "--input-schema",
+                "partner",
+            ])
+            .unwrap(),
+            Args {
+                input: PathBuf::from("input.csv"),
+                config: Some(PathBuf::from("config.toml")),
+                stranded_report: Some(PathBuf::from("stranded.csv")),
+                trial_balance: Some(PathBuf::from("trial.csv")),
+                risk_report: Some(PathBuf::from("risk.csv")),
+                input_schema: Some("partner".to_owned()),
+                ..Args::default()
+            }
+        );
+
+        assert_eq!(
+            args(&["statement", "--client", "42", "input.csv"]).unwrap(),
+            Args {
+                command: Command::Statement { cid: 42 },
+                input: PathBuf::from("input.csv"),
+                ..Args::default()
+            }
+        );
+
+        assert_eq!(
+            args(&[
+                "statement",
+                "--client",
+                "42",
+                "--tenant",
+                "brand",
+                "input.csv"
+            ])
+            .unwrap(),
+            Args {
+                command: Command::Statement { cid: 42 },
+                input: PathBuf::from("input.csv"),
+                tenant: Some("brand".to_owned()),
+                ..Args::default()
+            }
+        );
+
+        assert_eq!(
+            args(&[
+                "balance",
+                "--client",
+                "42",
+                "--at",
+                "1600000000",
+                "input.csv"
+            ])
+            .unwrap(),
+            Args {
+                command: Command::Balance {
+                    cid: 42,
+                    point: PointInTime::At(1_600_000_000)
+                },
+                input: PathBuf::from("input.csv"),
+                ..Args::default()
+            }
+        );
+
+        assert_eq!(
+            args(&["--fx-rates", "rates.csv", "input.csv"]).unwrap(),
+            Args {
+                input: PathBuf::from("input.csv"),
+                fx_rates: Some(PathBuf::from("rates.csv")),
+                ..Args::default()
+            }
+        );
+
+        assert_eq!(
+            args(&["--accrue-interest", "100:200", "input.csv"]).unwrap(),
+            Args {
+                input: PathBuf::from("input.csv"),
+                accrue_interest: Some(Period { from: 100, to: 200 }),
+                ..Args::default()
+            }
+        );
+
+        assert_eq!(
+            args(&[
+                "statement",
+                "--client",
+                "42",
+                "--log-format",
+                "json",
+                "input.csv"
+            ])
+            .unwrap(),
+            Args {
+                command: Command::Statement { cid: 42 },
+                input: PathBuf::from("input.csv"),
+                log_format: LogFormat::Json,
+                ..Args::default()
+            }
+        );
+
+        assert!(args(&[]).is_err());
+        assert!(args(&["--log-format", "xml", "input.csv"]).is_err());
+        assert!(args(&[
+            "statement",
+            "--client",
+            "42",
+            "--accrue-interest",
+            "100:200",
+            "input.csv"
+        ])
+        .is_err());
+        assert!(args(&["balance", "--client", "42", "input.csv"]).is_err());
+        assert!(args(&[
+            "statement",
+            "--client",
+            "42",
+            "--after-tx",
+            "1",
+            "input.csv"
+        ])
+        .is_err());
+        assert!(args(&["statement", "input.csv"]).is_err());
+        assert!(args(&["--client", "42", "input.csv"]).is_err());
+        assert!(args(&["--tenant", "brand", "input.csv"]).is_err());
+        assert!(args(&["--config"]).is_err());
+        assert!(args(&["--unknown", "input.csv"]).is_err());
+        assert!(args(&["input.csv", "other.csv"]).is_err());
+    }
+}
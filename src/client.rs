@@ -1,12 +1,15 @@
 use crate::decimal::Decimal;
-use anyhow::{anyhow, Result};
+use crate::output::store_csv;
+use crate::rejection::{reject, Reason};
+use crate::transaction::ClientId;
+use anyhow::Result;
 use serde::Serialize;
 
 /// Client info
 #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 #[serde(into = "OutputClient")]
 pub struct Client {
-    pub cid: u16,
+    pub cid: ClientId,
     pub available: Decimal,
     pub held: Decimal,
     pub locked: bool,
@@ -14,7 +17,7 @@ pub struct Client {
 
 impl Client {
     /// Creates new client from given id
-    pub fn new(cid: u16) -> Self {
+    pub fn new(cid: ClientId) -> Self {
         Self {
             cid,
             available: Decimal::new(0, 0),
@@ -26,7 +29,11 @@ impl Client {
     /// Returns error if client is locked
     pub fn ensure_unlocked(&self) -> Result<()> {
         if self.locked {
-            Err(anyhow!("Client is locked, client id: {}", self.cid))
+            Err(reject!(
+                Reason::ClientLocked,
+                "Client is locked, client id: {}",
+                self.cid
+            ))
         } else {
             Ok(())
         }
@@ -37,7 +44,7 @@ impl Client {
 #[derive(Debug, Serialize)]
 struct OutputClient {
     #[serde(rename = "client")]
-    cid: u16,
+    cid: ClientId,
     available: Decimal,
     held: Decimal,
     total: Decimal,
@@ -63,22 +70,75 @@ impl From<Client> for OutputClient {
     }
 }
 
+/// Client info labeled with tenant and currency, ready to be stored in output. Label is stored
+/// only if given, so it has to be given for all clients or for none of them.
+#[derive(Debug, Serialize)]
+struct LabeledOutputClient {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tenant: Option<String>,
+    #[serde(rename = "client")]
+    cid: ClientId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    currency: Option<String>,
+    available: Decimal,
+    held: Decimal,
+    total: Decimal,
+    locked: bool,
+}
+
 pub fn store_clients(
     writer: impl std::io::Write,
     clients: impl IntoIterator<Item = Client>,
 ) -> Result<()> {
-    let mut writer = csv::Writer::from_writer(writer);
+    store_csv(writer, clients)
+}
 
-    for client in clients {
-        writer.serialize(client)?
-    }
+/// Stores clients of many tenants, with tenant name in the first column - clients without tenant
+/// have it empty
+pub fn store_tenant_clients<T: AsRef<str>>(
+    writer: impl std::io::Write,
+    clients: impl IntoIterator<Item = (Option<T>, Client)>,
+) -> Result<()> {
+    let clients = clients
+        .into_iter()
+        .map(|(tenant, client)| (tenant, None::<T>, client));
+    store_labeled_clients(writer, true, None, clients)
+}
+
+/// Stores clients with tenant (if `tenants` is set) and currency (if `base` is given) columns.
+/// Clients without tenant have it empty, and balances in base currency are labeled with `base`
+/// (which may be empty as well).
+pub fn store_labeled_clients<T: AsRef<str>, C: AsRef<str>>(
+    writer: impl std::io::Write,
+    tenants: bool,
+    base: Option<&str>,
+    clients: impl IntoIterator<Item = (Option<T>, Option<C>, Client)>,
+) -> Result<()> {
+    let clients = clients.into_iter().map(|(tenant, currency, client)| {
+        let OutputClient {
+            cid,
+            available,
+            held,
+            total,
+            locked,
+        } = client.into();
+        LabeledOutputClient {
+            tenant: tenants.then(|| tenant.as_ref().map_or("", AsRef::as_ref).to_owned()),
+            cid,
+            currency: base.map(|base| currency.as_ref().map_or(base, AsRef::as_ref).to_owned()),
+            available,
+            held,
+            total,
+            locked,
+        }
+    });
 
-    Ok(())
+    store_csv(writer, clients)
 }
 
 #[cfg(test)]
 mod test {
-    use super::{store_clients, Client};
+    use super::{store_clients, store_labeled_clients, store_tenant_clients, Client};
     use crate::decimal::Decimal;
 
     #[test]
@@ -106,6 +166,61 @@ mod test {
             r#"client,available,held,total,locked
 1,1.5,0.0,1.5,false
 2,2.0,0.0,2.0,false
+"#
+        );
+    }
+
+    #[test]
+    fn store_tenants() {
+        let client = Client {
+            cid: 1,
+            available: Decimal::new(1, 5000),
+            held: Decimal::new(0, 0),
+            locked: false,
+        };
+
+        let mut buf = vec![];
+        store_tenant_clients(
+            std::io::Cursor::new(&mut buf),
+            vec![(None, client.clone()), (Some("brand"), client)],
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            r#"tenant,client,available,held,total,locked
+,1,1.5,0.0,1.5,false
+brand,1,1.5,0.0,1.5,false
+"#
+        );
+    }
+
+    #[test]
+    fn store_currencies() {
+        let client = Client {
+            cid: 1,
+            available: Decimal::new(1, 5000),
+            held: Decimal::new(0, 0),
+            locked: false,
+        };
+
+        let mut buf = vec![];
+        store_labeled_clients(
+            std::io::Cursor::new(&mut buf),
+            false,
+            Some(""),
+            vec![
+                (None::<&str>, None, client.clone()),
+                (None, Some("EUR"), client),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            r#"client,currency,available,held,total,locked
+1,,1.5,0.0,1.5,false
+1,EUR,1.5,0.0,1.5,false
 "#
         );
     }
@@ -0,0 +1,248 @@
+use crate::fees::Fees;
+use crate::fx::Fx;
+use crate::interest::Interest;
+use crate::rules::Rules;
+use crate::schema::InputSchema;
+use crate::transaction::DAY;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Application configuration, read from TOML file.
+///
+/// Every field has default value, so the file may contain only settings which are to be changed
+/// (and there is no need for the file at all if defaults are fine).
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Engine policy
+    pub policy: Policy,
+
+    /// Verifies engine invariants after every transaction, reporting violations
+    pub check_invariants: bool,
+
+    /// Transaction fees
+    pub fees: Fees,
+
+    /// Interest accrued on client funds, required for accrual
+    pub interest: Option<Interest>,
+
+    /// Currency conversion
+    pub fx: Fx,
+
+    /// Anti-abuse limits on client activity
+    pub rules: Rules,
+
+    /// Format of input file
+    pub input: InputSchema,
+
+    /// Other input formats, selected by name with `--input-schema`
+    pub inputs: HashMap<String, InputSchema>,
+
+    /// Engine settings of tenants, overriding the ones above
+    pub tenants: HashMap<String, TenantConfig>,
+}
+
+/// Engine settings of single tenant. Every section given replaces the whole section of main
+/// config, sections not given are taken from it.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TenantConfig {
+    pub policy: Option<Policy>,
+    pub check_invariants: Option<bool>,
+    pub fees: Option<Fees>,
+    pub interest: Option<Interest>,
+    pub rules: Option<Rules>,
+}
+
+impl Config {
+    /// Loads configuration from given file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&content)?)
+    }
+
+    /// Returns configuration of given tenant, the main one if it has no own settings
+    pub fn for_tenant(&self, tenant: Option<&str>) -> Config {
+        let tenant = match tenant.and_then(|tenant| self.tenants.get(tenant)) {
+            Some(tenant) => tenant.clone(),
+            None => return self.clone(),
+        };
+
+        Config {
+            policy: tenant.policy.unwrap_or_else(|| self.policy.clone()),
+            check_invariants: tenant.check_invariants.unwrap_or(self.check_invariants),
+            fees: tenant.fees.unwrap_or_else(|| self.fees.clone()),
+            interest: tenant.interest.or_else(|| self.interest.clone()),
+            rules: tenant.rules.unwrap_or_else(|| self.rules.clone()),
+            ..self.clone()
+        }
+    }
+
+    /// Returns input format of given name, or the default one
+    pub fn input_schema(&self, name: Option<&str>) -> Result<&InputSchema> {
+        match name {
+            Some(name) => self
+                .inputs
+                .get(name)
+                .ok_or_else(|| anyhow!("Unknown input schema: {}", name)),
+            None => Ok(&self.input),
+        }
+    }
+}
+
+/// Decisions on how engine handles situations which are not obvious
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Policy {
+    /// Allows resolving and charging back disputes on locked accounts.
+    ///
+    /// By default no transaction can be performed on locked client, but this way funds of any
+    /// other dispute opened before the account got locked stay held forever. Opening new disputes
+    /// on locked accounts is never allowed.
+    pub settle_disputes_on_locked: bool,
+
+    /// Unlocks client when chargeback which locked it is reversed, as long as there is no other
+    /// chargeback on this client which is not reversed.
+    pub unlock_on_reversal: bool,
+
+    /// Accrues interest also on locked clients. By default locked clients are skipped, as nothing
+    /// is performed on them.
+    pub accrue_interest_on_locked: bool,
+
+    /// Dispute lifecycle rules
+    pub dispute: DisputePolicy,
+}
+
+/// Rules for disputing transaction which was already disputed before
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisputePolicy {
+    /// Allows disputing transaction again after it was charged back
+    pub redispute_after_chargeback: bool,
+
+    /// Limits how many times transaction can be disputed again after its dispute was closed. No
+    /// limit if not set.
+    pub max_redisputes: Option<u32>,
+
+    /// Allows disputing transaction only within given number of days after it was performed. No
+    /// limit if not set.
+    #[serde(deserialize_with = "deserialize_days")]
+    pub window_days: Option<u64>,
+
+    /// Resolves disputes opened more than given number of days before the latest transaction, at
+    /// the end of processing. Disputes are never auto-resolved if not set.
+    #[serde(deserialize_with = "deserialize_days")]
+    pub auto_resolve_days: Option<u64>,
+}
+
+/// Days are compared as seconds, so they have to fit in timestamp
+fn deserialize_days<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<u64>, D::Error> {
+    let days = Option::<u64>::deserialize(deserializer)?;
+    match days {
+        Some(count) if count.checked_mul(DAY).is_none() => Err(serde::de::Error::custom(format!(
+            "Number of days too big: {}",
+            count
+        ))),
+        _ => Ok(days),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Config;
+    use crate::decimal::Decimal;
+    use crate::interest::DayCount;
+
+    #[test]
+    fn parse() {
+        let config: Config = toml::from_str(
+            r#"
+check_invariants = true
+
+[policy]
+settle_disputes_on_locked = true
+
+[policy.dispute]
+max_redisputes = 2
+window_days = 120
+
+[fees.deposit]
+type = "flat"
+amount = "0.5"
+
+[interest]
+annual_rate = "0.05"
+day_count = "30_360"
+
+[fx]
+base = "USD"
+spread = "0.01"
+
+[rules]
+max_withdrawal = "1000"
+withdrawal_frequency = { max_withdrawals = 3, transactions = 10 }
+
+[input]
+delimiter = ";"
+
+[inputs.partner]
+has_headers = false
+columns = ["client", "tx", "type", "amount"]
+
+[tenants.brand.policy]
+unlock_on_reversal = true
+
+[tenants.brand.rules]
+max_withdrawal = "10"
+"#,
+        )
+        .unwrap();
+        assert!(config.check_invariants);
+        assert!(config.policy.settle_disputes_on_locked);
+        assert!(!config.policy.dispute.redispute_after_chargeback);
+        assert_eq!(config.policy.dispute.max_redisputes, Some(2));
+        assert_eq!(config.policy.dispute.window_days, Some(120));
+        assert_eq!(config.policy.dispute.auto_resolve_days, None);
+        assert!(config.fees.deposit.is_some());
+        assert!(config.fees.withdrawal.is_none());
+        assert_eq!(
+            config.interest.as_ref().map(|interest| interest.day_count),
+            Some(DayCount::Thirty360)
+        );
+        assert_eq!(config.fx.base.as_deref(), Some("USD"));
+        assert_eq!(config.fx.spread, "0.01".parse().unwrap());
+        assert_eq!(config.rules.max_withdrawal, Some(Decimal::new(1000, 0)));
+        assert_eq!(config.rules.max_daily_withdrawal, None);
+        assert_eq!(config.input_schema(None).unwrap().delimiter, ';');
+        let partner = config.input_schema(Some("partner")).unwrap();
+        assert!(!partner.has_headers);
+        assert_eq!(partner.delimiter, ',');
+        assert!(config.input_schema(Some("other")).is_err());
+
+        let brand = config.for_tenant(Some("brand"));
+        assert!(brand.policy.unlock_on_reversal);
+        assert!(!brand.policy.settle_disputes_on_locked);
+        assert_eq!(brand.rules.max_withdrawal, Some(Decimal::new(10, 0)));
+        assert!(brand.check_invariants);
+        assert!(brand.fees.deposit.is_some());
+        let other = config.for_tenant(Some("other"));
+        assert!(other.policy.settle_disputes_on_locked);
+
+        let config: Config = toml::from_str("").unwrap();
+        assert!(!config.policy.settle_disputes_on_locked);
+        assert_eq!(config.fx.spread, Decimal::new(0, 0));
+
+        // Days have to fit in timestamp
+        assert!(toml::from_str::<Config>(
+            r#"
+[tenants.brand.policy.dispute]
+auto_resolve_days = 1000000000000000
+"#
+        )
+        .is_err());
+    }
+}
@@ -1,5 +1,6 @@
 use anyhow::{anyhow, Error, Result};
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use std::convert::TryFrom;
 use std::ops;
 
 /// Simple wrapper type to hold decimals value as fixed-point, as I refuse to perform financial
@@ -14,8 +15,8 @@ use std::ops;
 /// are typically 2-based fractional point, which would not allow represent all values precisely.
 /// Ensuring that crate is valid and efficient for this very case is way more expensive for this
 /// particular task, comparing to just deliver own solution.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd, Serialize, Deserialize)]
-#[serde(try_from = "&str", into = "String")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd, Serialize)]
+#[serde(into = "String")]
 pub struct Decimal(i64);
 
 impl Decimal {
@@ -23,6 +24,174 @@ impl Decimal {
     pub fn new(integral: i64, fractional: i64) -> Self {
         Self(integral * 10_000 + fractional)
     }
+
+    /// Multiplies two decimals (typically amount by rate), rounding result to 4 decimal places.
+    ///
+    /// Panics if result doesn't fit in decimal, same as integer arithmetic.
+    pub fn mul_round(self, other: Self, rounding: Rounding) -> Self {
+        // Product has 8 decimal places, it may not fit in 64 bits before rounding
+        let product = self.0 as i128 * other.0 as i128;
+        Self::from_scaled(rounding.div(product, 10_000))
+    }
+
+    /// Divides two decimals, rounding result to 4 decimal places.
+    ///
+    /// Panics on division by zero, or if result doesn't fit in decimal, same as integer arithmetic.
+    pub fn div_round(self, other: Self, rounding: Rounding) -> Self {
+        assert!(other.0 != 0, "Decimal division by zero");
+        Self::from_scaled(rounding.div(self.0 as i128 * 10_000, other.0 as i128))
+    }
+
+    /// Multiplies two decimals, and scales result by `numerator / denominator` (eg. rate by part of
+    /// the year), rounding only the final result to 4 decimal places. Returns `None` if result
+    /// doesn't fit in decimal.
+    ///
+    /// Panics if denominator is zero.
+    pub fn checked_mul_ratio(
+        self,
+        other: Self,
+        numerator: i64,
+        denominator: i64,
+        rounding: Rounding,
+    ) -> Option<Self> {
+        assert!(denominator != 0, "Decimal division by zero");
+        let product = (self.0 as i128 * other.0 as i128).checked_mul(numerator as i128)?;
+        i64::try_from(rounding.div(product, 10_000 * denominator as i128))
+            .ok()
+            .map(Self)
+    }
+
+    /// Splits amount into given number of parts, which are as equal as possible, and sum up
+    /// exactly to the amount. Leftover `0.0001` units go to the first parts.
+    ///
+    /// Panics if there are no parts, as amount cannot be split into nothing.
+    pub fn allocate(self, parts: usize) -> Vec<Self> {
+        assert!(parts > 0, "Cannot allocate decimal into zero parts");
+
+        let parts_i = parts as i64;
+        let (base, leftover) = (self.0 / parts_i, (self.0 % parts_i).unsigned_abs() as usize);
+        let unit = self.0.signum();
+
+        (0..parts)
+            .map(|idx| {
+                if idx < leftover {
+                    Self(base + unit)
+                } else {
+                    Self(base)
+                }
+            })
+            .collect()
+    }
+
+    fn from_scaled(value: i128) -> Self {
+        Self(i64::try_from(value).expect("Decimal overflow"))
+    }
+}
+
+/// Wide decimal, with the same precision as `Decimal`, for totals of many amounts (eg. sum of all
+/// clients deposits) which may not fit in `Decimal`. Summing `Decimal`s never overflows it in
+/// practice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd, Default, Serialize)]
+#[serde(into = "String")]
+pub struct WideDecimal(i128);
+
+impl From<Decimal> for WideDecimal {
+    fn from(decimal: Decimal) -> Self {
+        Self(decimal.0 as i128)
+    }
+}
+
+impl TryFrom<WideDecimal> for Decimal {
+    type Error = Error;
+
+    fn try_from(wide: WideDecimal) -> Result<Self> {
+        i64::try_from(wide.0)
+            .map(Self)
+            .map_err(|_| anyhow!("Decimal overflow: {}", wide))
+    }
+}
+
+impl ops::Add for WideDecimal {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self(self.0 + other.0)
+    }
+}
+
+impl ops::AddAssign for WideDecimal {
+    fn add_assign(&mut self, other: Self) {
+        self.0 += other.0
+    }
+}
+
+impl ops::Sub for WideDecimal {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self(self.0 - other.0)
+    }
+}
+
+impl std::iter::Sum for WideDecimal {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self(0), |sum, value| sum + value)
+    }
+}
+
+impl std::fmt::Display for WideDecimal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt_scaled(self.0, f)
+    }
+}
+
+impl From<WideDecimal> for String {
+    fn from(wide: WideDecimal) -> String {
+        wide.to_string()
+    }
+}
+
+/// Rounding mode of operations which results doesn't fit in 4 decimal places
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rounding {
+    /// Half to even, aka banker's rounding - default one, as it doesn't introduce bias when
+    /// summing up many rounded values
+    #[default]
+    HalfEven,
+    /// Half away from zero
+    HalfUp,
+    /// Towards negative infinity
+    Floor,
+    /// Towards positive infinity
+    Ceiling,
+}
+
+impl Rounding {
+    /// Divides integers, rounding the quotient
+    fn div(self, dividend: i128, divisor: i128) -> i128 {
+        let (dividend, divisor) = if divisor < 0 {
+            (-dividend, -divisor)
+        } else {
+            (dividend, divisor)
+        };
+
+        // Truncated towards zero, remainder has sign of dividend
+        let (quotient, remainder) = (dividend / divisor, dividend % divisor);
+        if remainder == 0 {
+            return quotient;
+        }
+
+        let away = quotient + remainder.signum();
+        match self {
+            Self::Floor => quotient.min(away),
+            Self::Ceiling => quotient.max(away),
+            Self::HalfUp if 2 * remainder.abs() >= divisor => away,
+            Self::HalfEven if 2 * remainder.abs() > divisor => away,
+            Self::HalfEven if 2 * remainder.abs() == divisor && quotient % 2 != 0 => away,
+            Self::HalfUp | Self::HalfEven => quotient,
+        }
+    }
 }
 
 impl ops::Add for Decimal {
@@ -61,28 +230,61 @@ impl ops::Neg for Decimal {
     }
 }
 
+/// Rounds half to even, use `mul_round` for other rounding modes
+impl ops::Mul for Decimal {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        self.mul_round(other, Rounding::HalfEven)
+    }
+}
+
+/// Rounds half to even, use `div_round` for other rounding modes
+impl ops::Div for Decimal {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self {
+        self.div_round(other, Rounding::HalfEven)
+    }
+}
+
+impl std::iter::Sum for Decimal {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self(0), |sum, value| sum + value)
+    }
+}
+
+impl<'a> std::iter::Sum<&'a Decimal> for Decimal {
+    fn sum<I: Iterator<Item = &'a Self>>(iter: I) -> Self {
+        iter.copied().sum()
+    }
+}
+
 impl std::fmt::Display for Decimal {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let Self(dec) = self;
-
-        let (s, dec) = if *dec < 0 { ("-", -dec) } else { ("", *dec) };
-        let l = dec / 10_000;
-        let mut r = dec % 10_000;
-
-        let fill = match r {
-            0 => "",
-            r if r < 10 => "000",
-            r if r < 100 => "00",
-            r if r < 1000 => "0",
-            _ => "",
-        };
+        fmt_scaled(self.0 as i128, f)
+    }
+}
 
-        while r % 10 == 0 && r != 0 {
-            r /= 10;
-        }
+/// Formats value scaled by 10^4 as decimal number
+fn fmt_scaled(dec: i128, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let (s, dec) = if dec < 0 { ("-", -dec) } else { ("", dec) };
+    let l = dec / 10_000;
+    let mut r = dec % 10_000;
 
-        write!(f, "{}{}.{}{}", s, l, fill, r)
+    let fill = match r {
+        0 => "",
+        r if r < 10 => "000",
+        r if r < 100 => "00",
+        r if r < 1000 => "0",
+        _ => "",
+    };
+
+    while r % 10 == 0 && r != 0 {
+        r /= 10;
     }
+
+    write!(f, "{}{}.{}{}", s, l, fill, r)
 }
 
 impl std::str::FromStr for Decimal {
@@ -115,7 +317,10 @@ impl std::str::FromStr for Decimal {
             return Err(anyhow!("More than one dot in decimal number"));
         }
 
-        Ok(Self(sign * (l * 10_000 + r)))
+        l.checked_mul(10_000)
+            .and_then(|l| l.checked_add(r))
+            .map(|value| Self(sign * value))
+            .ok_or_else(|| anyhow!("Decimal number too big"))
     }
 }
 
@@ -127,15 +332,45 @@ impl std::convert::TryFrom<&str> for Decimal {
     }
 }
 
-impl Into<String> for Decimal {
-    fn into(self) -> String {
-        self.to_string()
+/// Decimals are deserialized from strings (so they are never rounded by going through floats), or
+/// from integers, which are exact - it is convenient for configuration files.
+impl<'de> Deserialize<'de> for Decimal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct Visitor;
+
+        impl de::Visitor<'_> for Visitor {
+            type Value = Decimal;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "decimal number as string, or integer")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> std::result::Result<Decimal, E> {
+                value.parse().map_err(E::custom)
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> std::result::Result<Decimal, E> {
+                Ok(Decimal::new(value, 0))
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> std::result::Result<Decimal, E> {
+                Ok(Decimal::new(value as i64, 0))
+            }
+        }
+
+        deserializer.deserialize_str(Visitor)
+    }
+}
+
+impl From<Decimal> for String {
+    fn from(decimal: Decimal) -> String {
+        decimal.to_string()
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::Decimal;
+    use super::{Decimal, Rounding};
 
     #[test]
     fn display() {
@@ -162,5 +397,174 @@ mod test {
         assert_eq!(Decimal(-100_000_000), "-10000.0".parse().unwrap());
         assert_eq!(Decimal(-100_000_120), "-10000.012".parse().unwrap());
         assert_eq!(Decimal(100_000_000), "10000.00002".parse().unwrap());
+        assert!("1000000000000000.0".parse::<Decimal>().is_err());
+    }
+
+    /// Reference rounding of `value / 10`, written in terms of floor division, so it doesn't
+    /// share implementation with `Rounding`
+    fn reference(value: i64, rounding: Rounding) -> i64 {
+        let (floor, digit) = (value.div_euclid(10), value.rem_euclid(10));
+        let ceiling = if digit == 0 { floor } else { floor + 1 };
+
+        match rounding {
+            Rounding::Floor => floor,
+            Rounding::Ceiling => ceiling,
+            _ if digit < 5 => floor,
+            _ if digit > 5 => ceiling,
+            Rounding::HalfUp if value > 0 => ceiling,
+            Rounding::HalfUp => floor,
+            Rounding::HalfEven if floor % 2 == 0 => floor,
+            Rounding::HalfEven => ceiling,
+        }
+    }
+
+    #[test]
+    fn rounding_boundary() {
+        let tenth = Decimal(1_000);
+        let modes = [
+            Rounding::HalfEven,
+            Rounding::HalfUp,
+            Rounding::Floor,
+            Rounding::Ceiling,
+        ];
+
+        // Every possible 5th decimal place digit, on both sides of zero and around odd and even
+        // 4th decimal place
+        for value in -1_000..=1_000 {
+            for rounding in modes {
+                let expected = Decimal(reference(value, rounding));
+                assert_eq!(
+                    Decimal(value).mul_round(tenth, rounding),
+                    expected,
+                    "{} * 0.1, {:?}",
+                    Decimal(value),
+                    rounding
+                );
+                assert_eq!(
+                    Decimal(value).div_round(Decimal(100_000), rounding),
+                    expected,
+                    "{} / 10, {:?}",
+                    Decimal(value),
+                    rounding
+                );
+                assert_eq!(
+                    Decimal(-value).div_round(Decimal(-100_000), rounding),
+                    expected,
+                    "{} / -10, {:?}",
+                    Decimal(-value),
+                    rounding
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn mul_div() {
+        let rate: Decimal = "0.015".parse().unwrap();
+        assert_eq!(Decimal(100_000) * rate, Decimal(1_500));
+        // 0.0001 * 0.015 = 0.0000015
+        assert_eq!(Decimal(1) * rate, Decimal(0));
+        assert_eq!(Decimal(1).mul_round(rate, Rounding::Ceiling), Decimal(1));
+        // 0.01 * 0.015 = 0.00015
+        assert_eq!(Decimal(100) * rate, Decimal(2));
+        assert_eq!(Decimal(300) * rate, Decimal(4));
+        assert_eq!(Decimal(300).mul_round(rate, Rounding::HalfUp), Decimal(5));
+        assert_eq!(Decimal(-300).mul_round(rate, Rounding::HalfUp), Decimal(-5));
+
+        // Product exceeding 64 bits before rounding
+        let big = Decimal(i64::MAX / 20_000 * 10_000);
+        assert_eq!(big * Decimal(20_000) / Decimal(20_000), big);
+
+        // 1 / 3 and 2 / 3
+        assert_eq!(Decimal(10_000) / Decimal(30_000), Decimal(3_333));
+        assert_eq!(Decimal(20_000) / Decimal(30_000), Decimal(6_667));
+        assert_eq!(
+            Decimal(20_000).div_round(Decimal(30_000), Rounding::Floor),
+            Decimal(6_666)
+        );
+        assert_eq!(
+            Decimal(-20_000).div_round(Decimal(30_000), Rounding::Floor),
+            Decimal(-6_667)
+        );
+        assert_eq!(
+            Decimal(-20_000).div_round(Decimal(30_000), Rounding::Ceiling),
+            Decimal(-6_666)
+        );
+    }
+
+    #[test]
+    fn mul_ratio() {
+        let rate: Decimal = "0.05".parse().unwrap();
+        // 1000 * 0.05 * 10 / 365 = 1.369863...
+        assert_eq!(
+            Decimal(10_000_000).checked_mul_ratio(rate, 10, 365, Rounding::HalfEven),
+            Some(Decimal(13_699))
+        );
+        assert_eq!(
+            Decimal(10_000_000).checked_mul_ratio(rate, 10, 365, Rounding::Floor),
+            Some(Decimal(13_698))
+        );
+        // Rounding twice would give 0.0001 * 0.5 = 0.0 (half to even)
+        assert_eq!(
+            Decimal(1).checked_mul_ratio(Decimal(5_000), 3, 1, Rounding::HalfEven),
+            Some(Decimal(2))
+        );
+        assert_eq!(
+            Decimal(i64::MAX).checked_mul_ratio(rate, 365 * 100, 365, Rounding::HalfEven),
+            None
+        );
+        assert_eq!(
+            Decimal(i64::MAX).checked_mul_ratio(Decimal(i64::MAX), i64::MAX, 365, Rounding::Floor),
+            None
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn div_by_zero() {
+        let _ = Decimal(10_000) / Decimal(0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn mul_overflow() {
+        let _ = Decimal(i64::MAX) * Decimal(20_000);
+    }
+
+    #[test]
+    fn sum() {
+        let values = [Decimal(1), Decimal(20_000), Decimal(-5)];
+        assert_eq!(values.iter().sum::<Decimal>(), Decimal(19_996));
+        assert_eq!(values.iter().copied().sum::<Decimal>(), Decimal(19_996));
+        assert_eq!(std::iter::empty::<Decimal>().sum::<Decimal>(), Decimal(0));
+    }
+
+    #[test]
+    fn allocate() {
+        assert_eq!(
+            Decimal(100).allocate(3),
+            vec![Decimal(34), Decimal(33), Decimal(33)]
+        );
+        assert_eq!(
+            Decimal(-100).allocate(3),
+            vec![Decimal(-34), Decimal(-33), Decimal(-33)]
+        );
+        assert_eq!(
+            Decimal(2).allocate(4),
+            vec![Decimal(1), Decimal(1), Decimal(0), Decimal(0)]
+        );
+        assert_eq!(Decimal(7).allocate(1), vec![Decimal(7)]);
+
+        for value in -50..=50 {
+            for parts in 1..=7 {
+                let allocated = Decimal(value).allocate(parts);
+                assert_eq!(allocated.len(), parts);
+                assert_eq!(allocated.iter().sum::<Decimal>(), Decimal(value));
+
+                let min = allocated.iter().min().unwrap().0;
+                let max = allocated.iter().max().unwrap().0;
+                assert!(max - min <= 1, "{} into {}: {:?}", value, parts, allocated);
+            }
+        }
     }
 }
@@ -0,0 +1,154 @@
+use crate::config::DisputePolicy;
+use crate::rejection::{reject, Reason};
+use anyhow::Result;
+
+/// Dispute lifecycle state of single transaction.
+///
+/// Legal transitions are:
+/// * `Settled` -> `Disputed` on dispute
+/// * `Disputed` -> `Resolved` on resolve
+/// * `Disputed` -> `ChargedBack` on chargeback
+/// * `Resolved` -> `Disputed` on dispute, as long as re-disputes limit is not exceeded
+/// * `ChargedBack` -> `Disputed` on dispute, only if policy allows it (and the limit is not
+///   exceeded)
+/// * `ChargedBack` -> `Reversed` on chargeback reversal; this is terminal state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisputeState {
+    /// Transaction was never disputed
+    #[default]
+    Settled,
+    /// Dispute is open, funds are held
+    Disputed,
+    /// Last dispute was resolved, funds were released
+    Resolved,
+    /// Last dispute ended with chargeback, funds were withdrawn
+    ChargedBack,
+    /// Chargeback was reversed, funds were given back; transaction can't be disputed anymore
+    Reversed,
+}
+
+impl DisputeState {
+    /// Returns state after opening dispute, or error if transition is not allowed.
+    ///
+    /// `redisputes` is how many times transaction was already disputed again after dispute was
+    /// closed.
+    pub fn dispute(self, redisputes: u32, policy: &DisputePolicy) -> Result<Self> {
+        match self {
+            Self::Settled => Ok(Self::Disputed),
+            Self::Disputed => Err(reject!(
+                Reason::InvalidDisputeState,
+                "Transaction is disputed"
+            )),
+            Self::Reversed => Err(reject!(
+                Reason::InvalidDisputeState,
+                "Transaction chargeback was reversed"
+            )),
+            Self::ChargedBack if !policy.redispute_after_chargeback => Err(reject!(
+                Reason::InvalidDisputeState,
+                "Transaction was charged back"
+            )),
+            Self::Resolved | Self::ChargedBack => match policy.max_redisputes {
+                Some(max) if redisputes >= max => Err(reject!(
+                    Reason::InvalidDisputeState,
+                    "Transaction was already disputed again {} times",
+                    redisputes
+                )),
+                _ => Ok(Self::Disputed),
+            },
+        }
+    }
+
+    /// Returns state after resolving dispute, or error if transaction is not disputed
+    pub fn resolve(self) -> Result<Self> {
+        self.ensure_disputed()?;
+        Ok(Self::Resolved)
+    }
+
+    /// Returns state after charging back, or error if transaction is not disputed
+    pub fn chargeback(self) -> Result<Self> {
+        self.ensure_disputed()?;
+        Ok(Self::ChargedBack)
+    }
+
+    /// Returns state after reversing chargeback, or error if transaction is not charged back
+    pub fn reverse(self) -> Result<Self> {
+        if self == Self::ChargedBack {
+            Ok(Self::Reversed)
+        } else {
+            Err(reject!(
+                Reason::InvalidDisputeState,
+                "Transaction is not charged back"
+            ))
+        }
+    }
+
+    /// Ensures that dispute is open, returning error otherwise
+    fn ensure_disputed(self) -> Result<()> {
+        if self == Self::Disputed {
+            Ok(())
+        } else {
+            Err(reject!(
+                Reason::InvalidDisputeState,
+                "Transaction is not disputed"
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DisputeState;
+    use crate::config::DisputePolicy;
+
+    #[test]
+    fn transitions() {
+        let policy = DisputePolicy::default();
+
+        let state = DisputeState::Settled;
+        assert!(state.resolve().is_err());
+        assert!(state.chargeback().is_err());
+
+        let state = state.dispute(0, &policy).unwrap();
+        assert_eq!(state, DisputeState::Disputed);
+        assert!(state.dispute(0, &policy).is_err());
+
+        assert_eq!(state.resolve().unwrap(), DisputeState::Resolved);
+        assert_eq!(state.chargeback().unwrap(), DisputeState::ChargedBack);
+
+        assert_eq!(
+            DisputeState::Resolved.dispute(10, &policy).unwrap(),
+            DisputeState::Disputed
+        );
+        assert!(DisputeState::ChargedBack.dispute(0, &policy).is_err());
+
+        assert!(DisputeState::Settled.reverse().is_err());
+        assert!(DisputeState::Disputed.reverse().is_err());
+        assert!(DisputeState::Resolved.reverse().is_err());
+        let state = DisputeState::ChargedBack.reverse().unwrap();
+        assert_eq!(state, DisputeState::Reversed);
+        assert!(state.reverse().is_err());
+        assert!(state.resolve().is_err());
+        assert!(state.chargeback().is_err());
+    }
+
+    #[test]
+    fn transitions_limited() {
+        let policy = DisputePolicy {
+            redispute_after_chargeback: true,
+            max_redisputes: Some(1),
+            ..DisputePolicy::default()
+        };
+
+        assert_eq!(
+            DisputeState::Resolved.dispute(0, &policy).unwrap(),
+            DisputeState::Disputed
+        );
+        assert!(DisputeState::Resolved.dispute(1, &policy).is_err());
+        assert_eq!(
+            DisputeState::ChargedBack.dispute(0, &policy).unwrap(),
+            DisputeState::Disputed
+        );
+        assert!(DisputeState::ChargedBack.dispute(1, &policy).is_err());
+        assert!(DisputeState::Reversed.dispute(0, &policy).is_err());
+    }
+}
@@ -1,14 +1,31 @@
 use crate::client::Client;
-use crate::decimal::Decimal;
-use crate::transaction::Transaction;
+use crate::config::{Config, Policy};
+use crate::decimal::{Decimal, WideDecimal};
+use crate::dispute::DisputeState;
+use crate::fees::Fees;
+use crate::fx::{Conversion, Fx};
+use crate::interest::{Interest, Period};
+use crate::ledger::{Account, AccountBalance, Entry, Ledger};
+use crate::rejection::{reject, Reason};
+use crate::rules::{Rule, RuleChecker, Rules};
+use crate::transaction::{ClientId, Currency, Record, Timestamp, Transaction, TxId, DAY};
 use anyhow::{anyhow, Result};
-use log::warn;
-use std::collections::HashMap;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+use tracing::{error_span, info, warn};
+
+mod invariants;
+mod observer;
+
+pub use invariants::{Invariant, Violation};
+pub use observer::{EngineObserver, Event};
 
 /// Helper function returning error if client ids doesn't matc,
-fn cid_matches(expected: u16, occured: u16) -> Result<()> {
+fn cid_matches(expected: ClientId, occured: ClientId) -> Result<()> {
     if expected != occured {
-        Err(anyhow!(
+        Err(reject!(
+            Reason::ClientMismatch,
             "Client id doesn't match! Expected {}, but {} given",
             expected,
             occured
@@ -18,68 +35,157 @@ fn cid_matches(expected: u16, occured: u16) -> Result<()> {
     }
 }
 
-/// Processes all transactions and returns input.
+/// Processes all transactions and returns engine in its final state.
 ///
 /// I actually could (and maybe should) process iterator over `Transaction` with errors already
 /// handled, but I just don't want to keep all transactions in memory as it is not needed here, so
 /// I went this way to achieve lazy parsing.
-pub fn process(
-    transactions: impl IntoIterator<Item = Transaction>,
-) -> Result<impl Iterator<Item = Client>> {
-    let mut engine = Engine::new();
+pub fn process(records: impl IntoIterator<Item = Record>, config: &Config) -> Engine {
+    process_with(records, config, |_, _, _| ())
+}
 
-    for transaction in transactions {
-        if let Err(err) = engine.process_transaction(transaction) {
-            warn!("Rejecting transaction, reson: {}", err);
-        }
-    }
+/// Processes all transactions like `process`, calling `processed` after every transaction with
+/// engine state after it, and its result. Auto-resolved disputes are reported as resolve
+/// transactions without position.
+pub fn process_with(
+    records: impl IntoIterator<Item = Record>,
+    config: &Config,
+    processed: impl FnMut(&Engine, &Record, &Result<Outcome>),
+) -> Engine {
+    let mut engine = Engine::from_config(config);
+    engine.process_all(records, processed);
+    engine
+}
+
+/// What happened to transaction which was not rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Transaction was applied
+    Applied,
+    /// Transaction is exact re-submission of transaction applied before, so nothing was done
+    Duplicate,
+}
 
-    Ok(engine.into_clients())
+/// Dispute which was left open on locked client
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StrandedDispute {
+    #[serde(rename = "client")]
+    pub cid: ClientId,
+    pub tx: TxId,
+    pub amount: Decimal,
 }
 
 /// Single transaction entry
 #[derive(Debug)]
 struct HistoryEntry {
-    cid: u16,
+    cid: ClientId,
     // Negative for withdrawal
     amount: Decimal,
-    disputed: bool,
+    // Fee charged on top of amount
+    fee: Decimal,
+    // Currency of amount
+    currency: Currency,
+    // Target currency and result of conversion, for convert transaction - kept for audit
+    conversion: Option<(Currency, Conversion)>,
+    // Part of amount which is currently disputed (held)
+    disputed: Decimal,
+    // Part of amount which is neither disputed nor charged back
+    undisputed: Decimal,
+    // Part of amount which is charged back
+    charged_back: Decimal,
+    // When transaction was performed
+    timestamp: Option<Timestamp>,
+    // When last dispute was opened
+    disputed_at: Option<Timestamp>,
+    state: DisputeState,
+    // How many times transaction was disputed again after its dispute was closed
+    redisputes: u32,
+    // Interest accrued by engine, which is not a deposit so it cannot be disputed
+    interest: bool,
 }
 
 impl HistoryEntry {
     /// Ensures that entry is a deposit transaction, returning error otherwise
     fn ensure_deposit(&self) -> Result<()> {
-        if self.amount < Decimal::new(0, 0) {
-            Err(anyhow!("Transaction is not deposit"))
+        if self.amount < Decimal::new(0, 0) || self.interest {
+            Err(reject!(Reason::NotDeposit, "Transaction is not deposit"))
+        } else if self.currency.is_some() {
+            Err(reject!(
+                Reason::NotDeposit,
+                "Only deposits in base currency can be disputed"
+            ))
         } else {
             Ok(())
         }
     }
 
-    /// Ensures that entry is disputed, returning error otherwise
-    fn ensure_disputed(&self) -> Result<()> {
-        if !self.disputed {
-            Err(anyhow!("Transaction is not disputed"))
-        } else {
-            Ok(())
+    /// Ensures that dispute at given time is within dispute window after transaction, returning
+    /// error otherwise. If any of timestamps is unknown, dispute is allowed.
+    fn ensure_within_window(&self, at: Option<Timestamp>, window_days: Option<u64>) -> Result<()> {
+        match (self.timestamp, at, window_days) {
+            (Some(timestamp), Some(at), Some(days))
+                if at.saturating_sub(timestamp) > days.saturating_mul(DAY) =>
+            {
+                Err(reject!(
+                    Reason::DisputeWindow,
+                    "Transaction is older than {} days dispute window",
+                    days
+                ))
+            }
+            _ => Ok(()),
         }
     }
 
-    /// Esures that entry is *not* disputed, returning error otherwise
-    fn ensure_not_disputed(&self) -> Result<()> {
-        if self.disputed {
-            Err(anyhow!("Transaction is disputed"))
-        } else {
-            Ok(())
+    /// Returns amount to be disputed - whole undisputed amount if not given, or given part of it.
+    fn dispute_amount(&self, amount: Option<Decimal>) -> Result<Decimal> {
+        match amount {
+            None if self.undisputed > Decimal::new(0, 0) => Ok(self.undisputed),
+            None => Err(reject!(Reason::InvalidDisputeAmount, "Nothing left to dispute")),
+            Some(amount) if amount <= Decimal::new(0, 0) => {
+                Err(reject!(Reason::InvalidDisputeAmount, "Disputed amount has to be positive"))
+            }
+            Some(amount) if amount > self.undisputed => Err(reject!(
+                Reason::InvalidDisputeAmount,
+                "Disputed amount exceeds undisputed amount of transaction, disputed: {}, undisputed: {}",
+                amount,
+                self.undisputed
+            )),
+            Some(amount) => Ok(amount),
         }
     }
 }
 
-/// Internal engine implementation. Not exposed, as it is just used internally in `process` function.
+/// Transactions processing engine. Typically created and fed by `process` function.
 #[derive(Default, Debug)]
-struct Engine {
+pub struct Engine {
+    /// Policy for ambiguous situations
+    policy: Policy,
+
+    /// Fees charged on transactions
+    fees: Fees,
+
+    /// Anti-abuse rules verified before funds are moved
+    rules: RuleChecker,
+
+    /// Currency conversion settings and rates
+    fx: Fx,
+
     /// Clients accounts
-    clients: HashMap<u16, Client>,
+    ///
+    /// Balances are never modified directly, they are always recalculated from the ledger.
+    clients: HashMap<ClientId, Client>,
+
+    /// Available balances of clients in currencies other than the base one. As only base currency
+    /// deposits can be disputed, nothing is ever held in them. Like client balances, recalculated
+    /// from the ledger.
+    wallets: HashMap<ClientId, BTreeMap<Rc<str>, Decimal>>,
+
+    /// Ledger which actually keeps track of all funds
+    ledger: Ledger,
+
+    /// Latest timestamp seen in input - it is current time for time-based policies, as they should
+    /// give same results no matter when input is processed
+    now: Option<Timestamp>,
 
     /// Transactions history
     ///
@@ -92,41 +198,234 @@ struct Engine {
     ///
     /// It could be something more space efficient, but as long as transactions can not be in
     /// order, and even not every tx would be logged, this is the easiest way to handle it
-    history: HashMap<u32, HistoryEntry>,
+    history: HashMap<TxId, HistoryEntry>,
+
+    /// Number of charged back transactions of every client which were not reversed yet, so
+    /// reversal knows if client can be unlocked without looking through whole history
+    charged_back: HashMap<ClientId, u64>,
+
+    /// Invariants verification, if enabled
+    invariants: Option<invariants::InvariantChecker>,
+
+    /// Notified about every processed transaction
+    observers: observer::Observers,
 }
 
 impl Engine {
     /// Creates new engine
-    fn new() -> Self {
-        Self::default()
+    pub fn new(policy: Policy) -> Self {
+        Self {
+            policy,
+            ..Self::default()
+        }
+    }
+
+    /// Creates engine configured according to config
+    pub fn from_config(config: &Config) -> Self {
+        let engine = Self::new(config.policy.clone())
+            .with_fees(config.fees.clone())
+            .with_rules(config.rules.clone())
+            .with_fx(config.fx.clone());
+
+        if config.check_invariants {
+            engine.with_invariants()
+        } else {
+            engine
+        }
+    }
+
+    /// Processes all transactions, calling `processed` after every transaction with engine state
+    /// after it, and its result. Disputes are auto-resolved at the end, and reported as resolve
+    /// transactions without position.
+    pub fn process_all(
+        &mut self,
+        records: impl IntoIterator<Item = Record>,
+        mut processed: impl FnMut(&Engine, &Record, &Result<Outcome>),
+    ) {
+        let _batch = error_span!("batch").entered();
+
+        for record in records {
+            self.process_logged(record, &mut processed);
+        }
+        self.finish(processed);
+    }
+
+    /// Processes single transaction as a part of `process_all`, logging rejection
+    pub fn process_logged(
+        &mut self,
+        record: Record,
+        mut processed: impl FnMut(&Engine, &Record, &Result<Outcome>),
+    ) {
+        let result = self.process_record(record.clone());
+        let transaction = &record.transaction;
+        let position = record.position.as_ref();
+        if let Ok(Outcome::Duplicate) = &result {
+            info!(
+                tenant = record.tenant.as_deref(),
+                tx = transaction.tx(),
+                client = transaction.cid(),
+                r#type = transaction.ttype().as_str(),
+                line = position.map(|position| position.line),
+                byte = position.map(|position| position.byte),
+                "Ignoring duplicate transaction"
+            );
+        }
+        if let Err(err) = &result {
+            warn!(
+                tenant = record.tenant.as_deref(),
+                tx = transaction.tx(),
+                client = transaction.cid(),
+                r#type = transaction.ttype().as_str(),
+                line = position.map(|position| position.line),
+                byte = position.map(|position| position.byte),
+                reason = Reason::of(err).as_str(),
+                error = %err,
+                "Rejecting transaction"
+            );
+        }
+        processed(self, &record, &result);
+    }
+
+    /// Finishes `process_all` after the last transaction, auto-resolving disputes
+    pub fn finish(&mut self, mut processed: impl FnMut(&Engine, &Record, &Result<Outcome>)) {
+        for transaction in self.auto_resolve() {
+            info!(
+                tx = transaction.tx(),
+                client = transaction.cid(),
+                "Dispute auto-resolved"
+            );
+            processed(self, &transaction.into(), &Ok(Outcome::Applied));
+        }
+    }
+
+    /// Enables verifying invariants after every transaction.
+    ///
+    /// It makes processing slower, but violations are reported instead of being silently
+    /// propagated into results.
+    pub fn with_invariants(mut self) -> Self {
+        self.invariants = Some(Default::default());
+        self
+    }
+
+    /// Registers observer, which is notified about every processed transaction
+    pub fn with_observer(mut self, observer: impl EngineObserver + 'static) -> Self {
+        self.add_observer(Box::new(observer));
+        self
+    }
+
+    /// Registers observer on already created engine
+    pub fn add_observer(&mut self, observer: Box<dyn EngineObserver>) {
+        self.observers.add(observer);
+    }
+
+    /// Sets fees charged on transactions
+    pub fn with_fees(mut self, fees: Fees) -> Self {
+        self.fees = fees;
+        self
+    }
+
+    /// Sets anti-abuse rules
+    pub fn with_rules(mut self, rules: Rules) -> Self {
+        self.rules = RuleChecker::new(rules);
+        self
+    }
+
+    /// Sets currency conversion settings and rates
+    pub fn with_fx(mut self, fx: Fx) -> Self {
+        self.fx = fx;
+        self
     }
 
     /// Logs single transaction
-    fn log(&mut self, tx: u32, cid: u16, amount: Decimal) {
-        self.history.insert(
-            tx,
-            HistoryEntry {
-                cid,
-                amount,
-                disputed: false,
-            },
-        );
+    fn log(
+        &mut self,
+        tx: TxId,
+        cid: ClientId,
+        amount: Decimal,
+        fee: Decimal,
+        currency: Currency,
+        timestamp: Option<Timestamp>,
+    ) -> &mut HistoryEntry {
+        self.history.entry(tx).or_insert(HistoryEntry {
+            cid,
+            amount,
+            fee,
+            currency,
+            conversion: None,
+            disputed: Decimal::new(0, 0),
+            undisputed: amount,
+            charged_back: Decimal::new(0, 0),
+            timestamp,
+            disputed_at: None,
+            state: DisputeState::Settled,
+            redisputes: 0,
+            interest: false,
+        })
     }
 
     /// Gives access to particular client. Adds new client if accessed for the first time.
-    fn client(&mut self, cid: u16) -> &Client {
+    fn client(&mut self, cid: ClientId) -> &Client {
         self.clients.entry(cid).or_insert_with(|| Client::new(cid))
     }
 
     /// Gives mutable access to particular client. Adds new client if accessed for the first time.
-    fn client_mut(&mut self, cid: u16) -> &mut Client {
+    fn client_mut(&mut self, cid: ClientId) -> &mut Client {
         self.clients.entry(cid).or_insert_with(|| Client::new(cid))
     }
 
+    /// Moves base currency funds between ledger accounts, updating balances of affected clients
+    fn transfer(&mut self, from: Account, to: Account, amount: Decimal) -> Result<()> {
+        self.ledger.transfer(&None, from, to, amount)?;
+
+        for cid in from.client().into_iter().chain(to.client()) {
+            self.refresh(cid, &None);
+        }
+
+        Ok(())
+    }
+
+    /// Posts ledger entries, updating balances of affected clients
+    fn post(&mut self, entries: &[Entry]) -> Result<()> {
+        self.ledger.post(entries)?;
+
+        for entry in entries {
+            if let Some(cid) = entry.account.client() {
+                self.refresh(cid, &entry.currency);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Recalculates client balances in given currency from the ledger
+    fn refresh(&mut self, cid: ClientId, currency: &Currency) {
+        let available = self
+            .ledger
+            .client_balance(currency, Account::Available(cid));
+
+        match currency {
+            Some(currency) => {
+                self.wallets
+                    .entry(cid)
+                    .or_default()
+                    .insert(currency.clone(), available);
+            }
+            None => {
+                let held = self
+                    .ledger
+                    .client_balance(&None, Account::DisputeSuspense(cid));
+                let client = self.client_mut(cid);
+                client.available = available;
+                client.held = held;
+            }
+        }
+    }
+
     /// Ensures, that there is no given tx in history, returning error otherwise
-    fn ensure_unique(&self, tx: u32) -> Result<()> {
+    fn ensure_unique(&self, tx: TxId) -> Result<()> {
         if self.history.contains_key(&tx) {
-            Err(anyhow!(
+            Err(reject!(
+                Reason::DuplicateTx,
                 "Transaction with tx which was previously resolved, tx: {}",
                 tx
             ))
@@ -135,73 +434,265 @@ impl Engine {
         }
     }
 
+    /// Checks if transaction is exact re-submission of deposit or withdrawal applied before - same
+    /// type, client, tx, amount and currency
+    fn is_duplicate(&self, transaction: &Transaction, currency: &Currency) -> bool {
+        let (tx, cid, amount) = match *transaction {
+            Transaction::Deposit { tx, cid, amount } => (tx, cid, amount),
+            Transaction::Withdrawal { tx, cid, amount } => (tx, cid, -amount),
+            _ => return false,
+        };
+
+        self.history.get(&tx).is_some_and(|entry| {
+            entry.cid == cid
+                && entry.amount == amount
+                && entry.currency == *currency
+                && entry.conversion.is_none()
+                && !entry.interest
+        })
+    }
+
     /// Processes single transaction
     ///
     /// General thoughts:
     /// * Relative transactions (dispute/resolve/chargeback) contains client id, but it actually
-    /// can be infered from transaction id (as tx is globally unique). I decided, that if those
-    /// missmatch, transaction is invalid and rejected.
-    /// * Transactions cannot be performed on locked accounts. They are just rejected.
-    /// * Tx never colide, if they do - something went messy, transaction is rejected.
+    ///   can be infered from transaction id (as tx is globally unique). I decided, that if those
+    ///   missmatch, transaction is invalid and rejected.
+    /// * Transactions cannot be performed on locked accounts. They are just rejected. The only
+    ///   exception are resolves and chargebacks, if `settle_disputes_on_locked` policy is set,
+    ///   and chargeback reversals.
+    /// * Tx never colide, if they do - something went messy, transaction is rejected. The only
+    ///   exception is exact re-submission of deposit or withdrawal, which is ignored as duplicate,
+    ///   as upstream may retry sending it.
     /// * In doc there is something about freezing, but there is nothing about it anywhere else - I
-    /// assume frozen == locked.
+    ///   assume frozen == locked.
+    ///
+    /// * Time based policies uses input timestamps, not wall clock. Transactions without timestamp
+    ///   are never affected by them.
     ///
     /// Function returns `Result` when transaction is invalid and should be rejected, giving back
     /// rejection reason.
-    fn process_transaction(&mut self, transaction: Transaction) -> Result<()> {
+    pub fn process_record(
+        &mut self,
+        Record {
+            transaction,
+            timestamp,
+            currency,
+            ..
+        }: Record,
+    ) -> Result<Outcome> {
+        if timestamp > self.now {
+            self.now = timestamp;
+        }
+        let currency = self.fx.normalize(currency);
+
+        if self.invariants.is_none() && self.observers.is_empty() {
+            return self.apply_new(transaction, currency, timestamp);
+        }
+
+        let before = self.clients.get(&transaction.cid()).cloned();
+        let result = self.apply_new(transaction.clone(), currency, timestamp);
+
+        if let Some(mut invariants) = self.invariants.take() {
+            let was_locked = before.as_ref().is_some_and(|client| client.locked);
+            let applied = matches!(result, Ok(Outcome::Applied));
+            invariants.check(self, &transaction, was_locked, applied);
+            self.invariants = Some(invariants);
+        }
+
+        let mut observers = std::mem::take(&mut self.observers);
+        observers.notify(self, &transaction, timestamp, before.as_ref(), &result);
+        self.observers = observers;
+
+        result
+    }
+
+    /// Processes single transaction without timestamp
+    pub fn process_transaction(&mut self, transaction: Transaction) -> Result<Outcome> {
+        self.process_record(transaction.into())
+    }
+
+    /// Applies transaction, unless it is a duplicate
+    fn apply_new(
+        &mut self,
+        transaction: Transaction,
+        currency: Currency,
+        timestamp: Option<Timestamp>,
+    ) -> Result<Outcome> {
+        if self.is_duplicate(&transaction, &currency) {
+            return Ok(Outcome::Duplicate);
+        }
+
+        self.apply(transaction, currency, timestamp)?;
+        Ok(Outcome::Applied)
+    }
+
+    /// Applies transaction to the engine state. Currency matters only for deposits, withdrawals
+    /// and conversions - other transactions act on base currency.
+    fn apply(
+        &mut self,
+        transaction: Transaction,
+        currency: Currency,
+        timestamp: Option<Timestamp>,
+    ) -> Result<()> {
         match transaction {
-            Transaction::Deposit { tx, cid, amount } => self.process_deposit(tx, cid, amount)?,
+            Transaction::Deposit { tx, cid, amount } => {
+                self.process_deposit(tx, cid, amount, currency, timestamp)?
+            }
             Transaction::Withdrawal { tx, cid, amount } => {
-                self.process_whitdrawal(tx, cid, amount)?
+                self.process_whitdrawal(tx, cid, amount, currency, timestamp)?
+            }
+            Transaction::Dispute { tx, cid, amount } => {
+                self.process_dispute(tx, cid, amount, timestamp)?
             }
-            Transaction::Dispute { tx, cid } => self.process_dispute(tx, cid)?,
             Transaction::Resolve { tx, cid } => self.process_resolve(tx, cid)?,
             Transaction::Chargeback { tx, cid } => self.process_chargeback(tx, cid)?,
+            Transaction::ChargebackReversal { tx, cid } => {
+                self.process_chargeback_reversal(tx, cid)?
+            }
+            Transaction::Interest { tx, cid, amount } => {
+                self.process_interest(tx, cid, amount, timestamp)?
+            }
+            Transaction::Convert {
+                tx,
+                cid,
+                amount,
+                to,
+            } => {
+                let to = self.fx.normalize(to);
+                self.process_convert(tx, cid, amount, currency, to, timestamp)?
+            }
         }
 
         Ok(())
     }
 
     /// Processes deposit transaction
-    fn process_deposit(&mut self, tx: u32, cid: u16, amount: Decimal) -> Result<()> {
+    fn process_deposit(
+        &mut self,
+        tx: TxId,
+        cid: ClientId,
+        amount: Decimal,
+        currency: Currency,
+        timestamp: Option<Timestamp>,
+    ) -> Result<()> {
         self.ensure_unique(tx)?;
 
-        let client = self.client_mut(cid);
-        client.ensure_unlocked()?;
-        client.available += amount;
-        self.log(tx, cid, amount);
+        self.client(cid).ensure_unlocked()?;
+        self.rules.check_deposit(cid)?;
+
+        let fee = self.fees.deposit(cid, amount);
+        if fee > amount {
+            return Err(reject!(
+                Reason::FeeNotCovered,
+                "Deposit doesn't cover fee, tx: {}, cid: {}, amount: {}, fee: {}",
+                tx,
+                cid,
+                amount,
+                fee
+            ));
+        }
+
+        self.post(&[
+            Entry {
+                account: Account::CashIn,
+                currency: currency.clone(),
+                amount: -amount,
+            },
+            Entry {
+                account: Account::Available(cid),
+                currency: currency.clone(),
+                amount: amount - fee,
+            },
+            Entry {
+                account: Account::House,
+                currency: currency.clone(),
+                amount: fee,
+            },
+        ])?;
+        self.log(tx, cid, amount, fee, currency, timestamp);
+        self.rules.deposited(cid);
 
         Ok(())
     }
 
     /// Processes whithdrawal transaction
-    fn process_whitdrawal(&mut self, tx: u32, cid: u16, amount: Decimal) -> Result<()> {
+    fn process_whitdrawal(
+        &mut self,
+        tx: TxId,
+        cid: ClientId,
+        amount: Decimal,
+        currency: Currency,
+        timestamp: Option<Timestamp>,
+    ) -> Result<()> {
         self.ensure_unique(tx)?;
 
-        let client = self.client_mut(cid);
-        client.ensure_unlocked()?;
-        if client.available >= amount {
-            client.available -= amount;
+        self.client(cid).ensure_unlocked()?;
+        self.rules.check_withdrawal(cid, amount, timestamp)?;
+
+        let fee = self.fees.withdrawal(cid, amount);
+        // Compared wide, so huge amount is rejected instead of overflowing
+        let available = self.ledger.balance(&currency, Account::Available(cid));
+        if available >= WideDecimal::from(amount) + fee.into() {
+            self.post(&[
+                Entry {
+                    account: Account::Available(cid),
+                    currency: currency.clone(),
+                    amount: -(amount + fee),
+                },
+                Entry {
+                    account: Account::CashOut,
+                    currency: currency.clone(),
+                    amount,
+                },
+                Entry {
+                    account: Account::House,
+                    currency: currency.clone(),
+                    amount: fee,
+                },
+            ])?;
             // Cannot be disputed, but for avoiding collisions
-            self.log(tx, cid, -amount);
+            self.log(tx, cid, -amount, fee, currency, timestamp);
+            self.rules.withdrawn(cid, amount, timestamp);
             Ok(())
         } else {
-            Err(anyhow!(
-                "Trying to withdraw more than available, tx: {}, cid: {}, amount: {}",
+            Err(reject!(
+                Reason::InsufficientFunds,
+                "Trying to withdraw more than available, tx: {}, cid: {}, amount: {}, fee: {}",
                 tx,
                 cid,
-                amount
+                amount,
+                fee
             ))
         }
     }
 
     /// Processes dispute transaction
-    fn process_dispute(&mut self, tx: u32, cid: u16) -> Result<()> {
-        self.client(cid).ensure_unlocked()?;
+    ///
+    /// Dispute may hold only part of deposit, if amount is given. In such case resolve and
+    /// chargeback act only on disputed part, and the rest of deposit can be disputed later.
+    fn process_dispute(
+        &mut self,
+        tx: TxId,
+        cid: ClientId,
+        amount: Option<Decimal>,
+        timestamp: Option<Timestamp>,
+    ) -> Result<()> {
+        // Chargeback always locks client, so disputing charged back transaction again would never
+        // be possible if lock was checked first
+        let redispute = self.policy.dispute.redispute_after_chargeback
+            && self
+                .history
+                .get(&tx)
+                .is_some_and(|entry| entry.cid == cid && entry.state == DisputeState::ChargedBack);
+        if !redispute {
+            self.client(cid).ensure_unlocked()?;
+        }
 
-        let amount = match self.history.get_mut(&tx) {
+        match self.history.get_mut(&tx) {
             None => {
-                return Err(anyhow!(
+                return Err(reject!(
+                    Reason::UnknownTx,
                     "Transaction was not previously performed, tx: {}",
                     tx
                 ))
@@ -209,37 +700,68 @@ impl Engine {
             // Rejects if:
             // * client id missmatches
             // * transaction amount is negative (disallow disputing withdrawal)
-            // * transaction is already disputed
+            // * transaction is already disputed, or dispute policy disallows disputing it again
+            // * disputed amount is not positive, or exceeds what is left undisputed
+            // * dispute is out of dispute window
             Some(entry) => {
                 cid_matches(entry.cid, cid)?;
                 entry.ensure_deposit()?;
-                entry.ensure_not_disputed()?;
+                entry.ensure_within_window(timestamp, self.policy.dispute.window_days)?;
+                let state = entry
+                    .state
+                    .dispute(entry.redisputes, &self.policy.dispute)?;
+                let amount = entry.dispute_amount(amount)?;
 
-                // Setting this should be done only after dispute is fully processed, but from this
-                // point it can't fail, so this safes hash map lookup.
-                entry.disputed = true;
-                entry.amount
+                // This actually may put amount under 0 - for example if client deposits some
+                // money, then whithdraw some of them, and then for some reason deposit is being
+                // disputes. It is not clear if it is possible, but in such cases going into dept
+                // seems to be reasonable solution.
+                self.ledger.transfer(
+                    &None,
+                    Account::Available(cid),
+                    Account::DisputeSuspense(cid),
+                    amount,
+                )?;
+
+                // Entry is updated in place after everything which can fail, so this safes hash
+                // map lookup.
+                if entry.state != DisputeState::Settled {
+                    entry.redisputes += 1;
+                }
+                entry.state = state;
+                entry.disputed_at = timestamp;
+                entry.disputed = amount;
+                entry.undisputed -= amount;
             }
         };
 
-        let client = self.client_mut(cid);
+        if redispute {
+            if let Some(charged_back) = self.charged_back.get_mut(&cid) {
+                *charged_back = charged_back.saturating_sub(1);
+            }
+        }
 
-        // This actually may put amount under 0 - for example if client deposits some money, then
-        // whithdraw some of them, and then for some reason deposit is being disputes. It is not
-        // clear if it is possible, but in such cases going into dept seems to be reasonable
-        // solution.
-        client.available -= amount;
-        client.held += amount;
+        self.refresh(cid, &None);
         Ok(())
     }
 
+    /// Ensures that dispute can be resolved or charged back on given client
+    fn ensure_settleable(&mut self, cid: ClientId) -> Result<()> {
+        if self.policy.settle_disputes_on_locked {
+            Ok(())
+        } else {
+            self.client(cid).ensure_unlocked()
+        }
+    }
+
     /// Processes resolve
-    fn process_resolve(&mut self, tx: u32, cid: u16) -> Result<()> {
-        self.client(cid).ensure_unlocked()?;
+    fn process_resolve(&mut self, tx: TxId, cid: ClientId) -> Result<()> {
+        self.ensure_settleable(cid)?;
 
-        let amount = match self.history.get_mut(&tx) {
+        match self.history.get_mut(&tx) {
             None => {
-                return Err(anyhow!(
+                return Err(reject!(
+                    Reason::UnknownTx,
                     "Transaction was not previously performed, tx: {}",
                     tx
                 ))
@@ -249,31 +771,38 @@ impl Engine {
             // * transaction is not disputed
             Some(entry) => {
                 cid_matches(entry.cid, cid)?;
-                entry.ensure_disputed()?;
 
                 // It is never said directly that resolved dispute makes transaction not disputed
                 // anymore, but it is just logical and makes sense to me.
-                // Also setting this should be done only after dispute is fully processed,
-                // but from this point it can't fail, so this safes hash map lookup.
-                entry.disputed = false;
-                entry.amount
+                let state = entry.state.resolve()?;
+                self.ledger.transfer(
+                    &None,
+                    Account::DisputeSuspense(cid),
+                    Account::Available(cid),
+                    entry.disputed,
+                )?;
+
+                // Entry is updated in place after everything which can fail, so this safes hash
+                // map lookup.
+                entry.state = state;
+                entry.undisputed += entry.disputed;
+                entry.disputed = Decimal::new(0, 0);
             }
         };
 
-        let client = self.client_mut(cid);
-
-        client.available += amount;
-        client.held -= amount;
+        self.refresh(cid, &None);
         Ok(())
     }
 
     /// Process chargeback
-    fn process_chargeback(&mut self, tx: u32, cid: u16) -> Result<()> {
-        self.client(cid).ensure_unlocked()?;
+    fn process_chargeback(&mut self, tx: TxId, cid: ClientId) -> Result<()> {
+        self.ensure_settleable(cid)?;
 
-        let amount = match self.history.get_mut(&tx) {
+        let held = self.client(cid).held;
+        match self.history.get_mut(&tx) {
             None => {
-                return Err(anyhow!(
+                return Err(reject!(
+                    Reason::UnknownTx,
                     "Transaction was not previously performed, tx: {}",
                     tx
                 ))
@@ -281,117 +810,1637 @@ impl Engine {
             // Rejects if:
             // * client id missmatches
             // * transaction is not disputed
+            // * client holds less than disputed amount - this should be impossible, as held is
+            //   increased only by disputing transactions, but if it happens, it is safer to
+            //   reject than to bring whole processing down
             Some(entry) => {
                 cid_matches(entry.cid, cid)?;
-                entry.ensure_disputed()?;
+                if held < entry.disputed {
+                    return Err(reject!(
+                        Reason::InsufficientHeld,
+                        "Client holds less than disputed amount, tx: {}, held: {}, disputed: {}",
+                        tx,
+                        held,
+                        entry.disputed
+                    ));
+                }
 
-                // It is never said directly that resolved dispute makes transaction not disputed
-                // anymore, but it is just logical and makes sense to me.
-                // Also setting this should be done only after dispute is fully processed,
-                // but from this point it can't fail, so this safes hash map lookup.
-                entry.disputed = false;
-                entry.amount
+                let state = entry.state.chargeback()?;
+                self.ledger.transfer(
+                    &None,
+                    Account::DisputeSuspense(cid),
+                    Account::ChargebackLoss,
+                    entry.disputed,
+                )?;
+
+                // Entry is updated in place after everything which can fail, so this safes hash
+                // map lookup.
+                entry.state = state;
+                entry.charged_back += entry.disputed;
+                entry.disputed = Decimal::new(0, 0);
             }
         };
 
-        let client = self.client_mut(cid);
-
-        // This should be impossible to have held being less than charged back amount, as held is
-        // increased only by disputing transactions.
-        assert!(client.held >= amount);
-        client.held -= amount;
-        client.locked = true;
+        self.refresh(cid, &None);
+        *self.charged_back.entry(cid).or_default() += 1;
+        self.client_mut(cid).locked = true;
 
         Ok(())
     }
 
-    /// Converts it to clients info (for results extraction)
-    fn into_clients(self) -> impl Iterator<Item = Client> {
-        self.clients.into_iter().map(|(_, client)| client)
-    }
-}
+    /// Processes chargeback reversal
+    ///
+    /// Reversal is allowed on locked client, as it is typically the chargeback which locked it.
+    fn process_chargeback_reversal(&mut self, tx: TxId, cid: ClientId) -> Result<()> {
+        match self.history.get_mut(&tx) {
+            None => {
+                return Err(reject!(
+                    Reason::UnknownTx,
+                    "Transaction was not previously performed, tx: {}",
+                    tx
+                ))
+            }
+            // Rejects if:
+            // * client id missmatches
+            // * transaction is not charged back
+            Some(entry) => {
+                cid_matches(entry.cid, cid)?;
 
-#[cfg(test)]
-mod test {
-    use super::*;
+                let state = entry.state.reverse()?;
+                self.ledger.transfer(
+                    &None,
+                    Account::ChargebackLoss,
+                    Account::Available(cid),
+                    entry.charged_back,
+                )?;
 
-    fn transactions_test(transactions: impl IntoIterator<Item = Transaction>) -> Engine {
-        let mut engine = Engine::new();
-        for transaction in transactions {
-            engine.process_transaction(transaction).ok();
+                // Entry is updated in place after everything which can fail, so this safes hash
+                // map lookup.
+                entry.state = state;
+                entry.charged_back = Decimal::new(0, 0);
+            }
+        };
+
+        let charged_back = self.charged_back.entry(cid).or_default();
+        *charged_back = charged_back.saturating_sub(1);
+        let unlock = self.policy.unlock_on_reversal && *charged_back == 0;
+
+        self.refresh(cid, &None);
+        if unlock {
+            self.client_mut(cid).locked = false;
         }
 
-        engine
+        Ok(())
     }
 
-    #[test]
-    fn dispute_siple() {
-        let transactions = vec![
-            Transaction::Deposit {
-                cid: 1,
-                tx: 1,
-                amount: Decimal::new(100, 0),
-            },
-            Transaction::Withdrawal {
-                cid: 1,
-                tx: 2,
-                amount: Decimal::new(50, 0),
-            },
-            Transaction::Deposit {
-                cid: 1,
-                tx: 3,
-                amount: Decimal::new(200, 0),
-            },
-            Transaction::Dispute { cid: 1, tx: 1 },
-        ];
+    /// Processes interest accrued by engine
+    fn process_interest(
+        &mut self,
+        tx: TxId,
+        cid: ClientId,
+        amount: Decimal,
+        timestamp: Option<Timestamp>,
+    ) -> Result<()> {
+        self.ensure_unique(tx)?;
 
-        let engine = transactions_test(transactions);
-        assert_eq!(
-            *engine.clients.get(&1).unwrap(),
-            Client {
-                cid: 1,
-                available: Decimal::new(150, 0),
-                held: Decimal::new(100, 0),
-                locked: false,
-            }
-        );
+        if !self.policy.accrue_interest_on_locked {
+            self.client(cid).ensure_unlocked()?;
+        }
+
+        self.transfer(Account::InterestExpense, Account::Available(cid), amount)?;
+        self.log(tx, cid, amount, Decimal::new(0, 0), None, timestamp)
+            .interest = true;
+
+        Ok(())
     }
 
-    #[test]
-    fn dispute_into_dept() {
-        let transactions = vec![
-            Transaction::Deposit {
-                cid: 1,
-                tx: 1,
-                amount: Decimal::new(100, 0),
+    /// Processes conversion of client funds between currencies.
+    ///
+    /// Amount goes through `Exchange` account in both currencies, so the ledger is balanced in
+    /// every currency. Spread is kept by the house, in the currency amount is converted into.
+    fn process_convert(
+        &mut self,
+        tx: TxId,
+        cid: ClientId,
+        amount: Decimal,
+        from: Currency,
+        to: Currency,
+        timestamp: Option<Timestamp>,
+    ) -> Result<()> {
+        self.ensure_unique(tx)?;
+
+        self.client(cid).ensure_unlocked()?;
+
+        // Rate effective at the latest time seen, if transaction has no timestamp
+        let conversion = self
+            .fx
+            .convert(amount, &from, &to, timestamp.or(self.now))?;
+        if self.ledger.client_balance(&from, Account::Available(cid)) < amount {
+            return Err(reject!(
+                Reason::InsufficientFunds,
+                "Trying to convert more than available, tx: {}, cid: {}, amount: {}",
+                tx,
+                cid,
+                amount
+            ));
+        }
+
+        self.post(&[
+            Entry {
+                account: Account::Available(cid),
+                currency: from.clone(),
+                amount: -amount,
             },
-            Transaction::Withdrawal {
-                cid: 1,
-                tx: 2,
-                amount: Decimal::new(50, 0),
+            Entry {
+                account: Account::Exchange,
+                currency: from.clone(),
+                amount,
             },
-            Transaction::Deposit {
-                cid: 1,
-                tx: 3,
-                amount: Decimal::new(200, 0),
+            Entry {
+                account: Account::Exchange,
+                currency: to.clone(),
+                amount: -(conversion.converted + conversion.spread),
             },
-            Transaction::Withdrawal {
-                cid: 1,
-                tx: 4,
-                amount: Decimal::new(200, 0),
+            Entry {
+                account: Account::Available(cid),
+                currency: to.clone(),
+                amount: conversion.converted,
             },
-            Transaction::Dispute { cid: 1, tx: 1 },
-        ];
+            Entry {
+                account: Account::House,
+                currency: to.clone(),
+                amount: conversion.spread,
+            },
+        ])?;
+        // Logged as taken from the source currency, so it cannot be disputed
+        self.log(tx, cid, -amount, Decimal::new(0, 0), from, timestamp)
+            .conversion = Some((to, conversion));
 
-        let engine = transactions_test(transactions);
-        assert_eq!(
-            *engine.clients.get(&1).unwrap(),
-            Client {
-                cid: 1,
-                available: Decimal::new(-50, 0),
-                held: Decimal::new(100, 0),
-                locked: false,
+        Ok(())
+    }
+
+    /// Accrues interest on available funds of every client over the period.
+    ///
+    /// Intended to be called when all transactions are processed, as balances at the time of
+    /// calling are used for the whole period. Every accrual is a separate interest transaction,
+    /// with tx following the highest tx seen so far, timestamped with the end of the period.
+    /// Returns interest transactions which were applied, ordered by client. Accruals which were
+    /// rejected (or which would overflow) are logged as warnings. Fails without accruing anything if there are not enough
+    /// tx left above the highest one.
+    pub fn accrue_interest(
+        &mut self,
+        interest: &Interest,
+        period: Period,
+    ) -> Result<Vec<Transaction>> {
+        let mut accruals: Vec<_> = self
+            .clients
+            .values()
+            .filter(|client| !client.locked || self.policy.accrue_interest_on_locked)
+            .map(|client| (client.cid, interest.accrued(client.available, period)))
+            .filter(|(_, amount)| !matches!(amount, Ok(amount) if *amount <= Decimal::new(0, 0)))
+            .collect();
+        accruals.sort_unstable_by_key(|(cid, _)| *cid);
+
+        let first_tx = match self.history.keys().max() {
+            Some(tx) => tx.checked_add(1),
+            None => Some(1),
+        };
+        // First tx is at least 1, so counting ids left never overflows
+        let txs = match first_tx {
+            Some(first_tx) if TxId::MAX - first_tx + 1 >= accruals.len() as u64 => {
+                first_tx..=TxId::MAX
+            }
+            _ if accruals.is_empty() => return Ok(vec![]),
+            _ => {
+                return Err(anyhow!(
+                    "Not enough tx left above the highest one for {} interest transactions",
+                    accruals.len()
+                ))
             }
+        };
+
+        let mut accrued = vec![];
+        for ((cid, amount), tx) in accruals.into_iter().zip(txs) {
+            let amount = match amount {
+                Ok(amount) => amount,
+                Err(err) => {
+                    warn!(
+                        tx,
+                        client = cid,
+                        reason = Reason::of(&err).as_str(),
+                        error = %err,
+                        "Rejecting interest accrual"
+                    );
+                    continue;
+                }
+            };
+
+            let transaction = Transaction::Interest { cid, tx, amount };
+            let result = self.process_record(Record {
+                transaction: transaction.clone(),
+                timestamp: Some(period.to),
+                position: None,
+                tenant: None,
+                currency: None,
+            });
+
+            match result {
+                Ok(_) => accrued.push(transaction),
+                Err(err) => warn!(
+                    tx,
+                    client = cid,
+                    amount = %amount,
+                    reason = Reason::of(&err).as_str(),
+                    error = %err,
+                    "Rejecting interest accrual"
+                ),
+            }
+        }
+
+        Ok(accrued)
+    }
+
+    /// Resolves disputes which are open for longer than `auto_resolve_days` policy allows.
+    ///
+    /// Intended to be called when all transactions are processed. Returns resolve transactions
+    /// which were applied, ordered by tx.
+    pub fn auto_resolve(&mut self) -> Vec<Transaction> {
+        let (now, days) = match (self.now, self.policy.dispute.auto_resolve_days) {
+            (Some(now), Some(days)) => (now, days),
+            _ => return vec![],
+        };
+
+        let mut expired: Vec<_> = self
+            .history
+            .iter()
+            .filter(|(_, entry)| entry.state == DisputeState::Disputed)
+            .filter(|(_, entry)| {
+                entry.disputed_at.is_some_and(|disputed_at| {
+                    now.saturating_sub(disputed_at) > days.saturating_mul(DAY)
+                })
+            })
+            .map(|(tx, entry)| (*tx, entry.cid))
+            .collect();
+        expired.sort_unstable();
+
+        // Resolve may still be rejected - if client is locked and policy doesn't allow it
+        expired
+            .into_iter()
+            .map(|(tx, cid)| Transaction::Resolve { cid, tx })
+            .filter(|resolve| self.process_transaction(resolve.clone()).is_ok())
+            .collect()
+    }
+
+    /// Returns the latest timestamp seen so far
+    pub fn now(&self) -> Option<Timestamp> {
+        self.now
+    }
+
+    /// Returns client state, `None` if client never appeared
+    pub fn client_state(&self, cid: ClientId) -> Option<&Client> {
+        self.clients.get(&cid)
+    }
+
+    /// Returns client which performed transaction, `None` if there is no such transaction in
+    /// history
+    pub fn transaction_client(&self, tx: TxId) -> Option<ClientId> {
+        self.history.get(&tx).map(|entry| entry.cid)
+    }
+
+    /// Returns fee charged on transaction, `None` if there is no such transaction in history
+    pub fn transaction_fee(&self, tx: TxId) -> Option<Decimal> {
+        self.history.get(&tx).map(|entry| entry.fee)
+    }
+
+    /// Returns rate transaction was converted with, `None` if there is no such conversion in
+    /// history
+    pub fn transaction_rate(&self, tx: TxId) -> Option<Decimal> {
+        self.history
+            .get(&tx)
+            .and_then(|entry| entry.conversion.as_ref())
+            .map(|(_, conversion)| conversion.rate)
+    }
+
+    /// Returns dispute lifecycle state of transaction, `None` if there is no such transaction in
+    /// history
+    pub fn transaction_state(&self, tx: TxId) -> Option<DisputeState> {
+        self.history.get(&tx).map(|entry| entry.state)
+    }
+
+    /// Returns number of transactions rejected by every anti-abuse rule, ordered by rule
+    pub fn rule_violations(&self) -> Vec<(Rule, u64)> {
+        self.rules.violations()
+    }
+
+    /// Returns invariant violations found so far, empty if invariants are not verified
+    pub fn violations(&self) -> &[Violation] {
+        self.invariants
+            .as_ref()
+            .map_or(&[], |invariants| invariants.violations())
+    }
+
+    /// Returns balances of all ledger accounts, ordered by account
+    pub fn trial_balance(&self) -> Vec<AccountBalance> {
+        self.ledger.balances()
+    }
+
+    /// Ensures that ledger is balanced, and that clients balances match it
+    pub fn check_books(&self) -> Result<()> {
+        self.ledger.ensure_balanced()?;
+
+        for client in self.clients.values() {
+            let available = self
+                .ledger
+                .client_balance(&None, Account::Available(client.cid));
+            let held = self
+                .ledger
+                .client_balance(&None, Account::DisputeSuspense(client.cid));
+
+            if client.available != available || client.held != held {
+                return Err(anyhow!(
+                    "Client balances doesn't match ledger, cid: {}, available: {} (ledger: {}), held: {} (ledger: {})",
+                    client.cid,
+                    client.available,
+                    available,
+                    client.held,
+                    held
+                ));
+            }
+        }
+
+        for (cid, wallet) in &self.wallets {
+            for (currency, available) in wallet {
+                let ledger = self
+                    .ledger
+                    .client_balance(&Some(currency.clone()), Account::Available(*cid));
+
+                if *available != ledger {
+                    return Err(anyhow!(
+                        "Client balance doesn't match ledger, cid: {}, currency: {}, available: {} (ledger: {})",
+                        cid,
+                        currency,
+                        available,
+                        ledger
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns disputes which are still open on locked clients, ordered by tx
+    pub fn stranded_disputes(&self) -> Vec<StrandedDispute> {
+        let mut disputes: Vec<_> = self
+            .history
+            .iter()
+            .filter(|(_, entry)| entry.state == DisputeState::Disputed)
+            .filter(|(_, entry)| self.clients.get(&entry.cid).is_some_and(|c| c.locked))
+            .map(|(tx, entry)| StrandedDispute {
+                cid: entry.cid,
+                tx: *tx,
+                amount: entry.disputed,
+            })
+            .collect();
+
+        disputes.sort_by_key(|dispute| dispute.tx);
+        disputes
+    }
+
+    /// Converts it to clients info (for results extraction)
+    pub fn into_clients(self) -> impl Iterator<Item = Client> {
+        self.clients.into_values()
+    }
+
+    /// Converts it to clients info in every currency they hold - the base one first, and then
+    /// others ordered by currency. Balance in other currency is never held, and client is locked in
+    /// all of them.
+    pub fn into_currency_clients(mut self) -> impl Iterator<Item = (Currency, Client)> {
+        let mut wallets = std::mem::take(&mut self.wallets);
+
+        self.clients.into_values().flat_map(move |client| {
+            let (cid, locked) = (client.cid, client.locked);
+            let wallet = wallets.remove(&cid).unwrap_or_default();
+
+            std::iter::once((None, client)).chain(wallet.into_iter().map(
+                move |(currency, available)| {
+                    let client = Client {
+                        available,
+                        locked,
+                        ..Client::new(cid)
+                    };
+                    (Some(currency), client)
+                },
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::DisputePolicy;
+
+    fn transactions_test(transactions: impl IntoIterator<Item = Transaction>) -> Engine {
+        transactions_test_with(transactions, Policy::default())
+    }
+
+    fn transactions_test_with(
+        transactions: impl IntoIterator<Item = Transaction>,
+        policy: Policy,
+    ) -> Engine {
+        let mut engine = Engine::new(policy);
+        for transaction in transactions {
+            engine.process_transaction(transaction).ok();
+        }
+
+        engine
+    }
+
+    #[test]
+    fn dispute_siple() {
+        let transactions = vec![
+            Transaction::Deposit {
+                cid: 1,
+                tx: 1,
+                amount: Decimal::new(100, 0),
+            },
+            Transaction::Withdrawal {
+                cid: 1,
+                tx: 2,
+                amount: Decimal::new(50, 0),
+            },
+            Transaction::Deposit {
+                cid: 1,
+                tx: 3,
+                amount: Decimal::new(200, 0),
+            },
+            Transaction::Dispute {
+                cid: 1,
+                tx: 1,
+                amount: None,
+            },
+        ];
+
+        let engine = transactions_test(transactions);
+        assert_eq!(
+            *engine.clients.get(&1).unwrap(),
+            Client {
+                cid: 1,
+                available: Decimal::new(150, 0),
+                held: Decimal::new(100, 0),
+                locked: false,
+            }
+        );
+    }
+
+    #[test]
+    fn dispute_into_dept() {
+        let transactions = vec![
+            Transaction::Deposit {
+                cid: 1,
+                tx: 1,
+                amount: Decimal::new(100, 0),
+            },
+            Transaction::Withdrawal {
+                cid: 1,
+                tx: 2,
+                amount: Decimal::new(50, 0),
+            },
+            Transaction::Deposit {
+                cid: 1,
+                tx: 3,
+                amount: Decimal::new(200, 0),
+            },
+            Transaction::Withdrawal {
+                cid: 1,
+                tx: 4,
+                amount: Decimal::new(200, 0),
+            },
+            Transaction::Dispute {
+                cid: 1,
+                tx: 1,
+                amount: None,
+            },
+        ];
+
+        let engine = transactions_test(transactions);
+        assert_eq!(
+            *engine.clients.get(&1).unwrap(),
+            Client {
+                cid: 1,
+                available: Decimal::new(-50, 0),
+                held: Decimal::new(100, 0),
+                locked: false,
+            }
+        );
+    }
+
+    /// Two disputes opened, first is charged back, locking the client
+    fn locking_transactions() -> Vec<Transaction> {
+        vec![
+            Transaction::Deposit {
+                cid: 1,
+                tx: 1,
+                amount: Decimal::new(100, 0),
+            },
+            Transaction::Deposit {
+                cid: 1,
+                tx: 2,
+                amount: Decimal::new(50, 0),
+            },
+            Transaction::Dispute {
+                cid: 1,
+                tx: 1,
+                amount: None,
+            },
+            Transaction::Dispute {
+                cid: 1,
+                tx: 2,
+                amount: None,
+            },
+            Transaction::Chargeback { cid: 1, tx: 1 },
+        ]
+    }
+
+    #[test]
+    fn settle_on_locked_rejected() {
+        let mut transactions = locking_transactions();
+        transactions.push(Transaction::Resolve { cid: 1, tx: 2 });
+
+        let engine = transactions_test(transactions);
+        assert_eq!(
+            *engine.clients.get(&1).unwrap(),
+            Client {
+                cid: 1,
+                available: Decimal::new(0, 0),
+                held: Decimal::new(50, 0),
+                locked: true,
+            }
+        );
+        assert_eq!(
+            engine.stranded_disputes(),
+            vec![StrandedDispute {
+                cid: 1,
+                tx: 2,
+                amount: Decimal::new(50, 0),
+            }]
+        );
+    }
+
+    #[test]
+    fn settle_on_locked_allowed() {
+        let policy = Policy {
+            settle_disputes_on_locked: true,
+            ..Policy::default()
+        };
+
+        let mut transactions = locking_transactions();
+        transactions.push(Transaction::Resolve { cid: 1, tx: 2 });
+        let engine = transactions_test_with(transactions, policy.clone());
+        assert_eq!(
+            *engine.clients.get(&1).unwrap(),
+            Client {
+                cid: 1,
+                available: Decimal::new(50, 0),
+                held: Decimal::new(0, 0),
+                locked: true,
+            }
+        );
+        assert!(engine.stranded_disputes().is_empty());
+
+        let mut transactions = locking_transactions();
+        transactions.push(Transaction::Chargeback { cid: 1, tx: 2 });
+        let engine = transactions_test_with(transactions, policy.clone());
+        assert_eq!(
+            *engine.clients.get(&1).unwrap(),
+            Client {
+                cid: 1,
+                available: Decimal::new(0, 0),
+                held: Decimal::new(0, 0),
+                locked: true,
+            }
+        );
+
+        // New disputes are still not allowed
+        let mut transactions = locking_transactions();
+        transactions.push(Transaction::Deposit {
+            cid: 1,
+            tx: 3,
+            amount: Decimal::new(10, 0),
+        });
+        transactions.push(Transaction::Dispute {
+            cid: 1,
+            tx: 3,
+            amount: None,
+        });
+        let engine = transactions_test_with(transactions, policy);
+        assert_eq!(engine.clients.get(&1).unwrap().held, Decimal::new(50, 0));
+    }
+
+    #[test]
+    fn redispute_after_chargeback() {
+        let transactions = vec![
+            Transaction::Deposit {
+                cid: 1,
+                tx: 1,
+                amount: Decimal::new(100, 0),
+            },
+            Transaction::Dispute {
+                cid: 1,
+                tx: 1,
+                amount: Some(Decimal::new(40, 0)),
+            },
+            Transaction::Chargeback { cid: 1, tx: 1 },
+        ];
+        let redispute = Transaction::Dispute {
+            cid: 1,
+            tx: 1,
+            amount: None,
+        };
+
+        // Locked client can't dispute by default
+        let mut engine = transactions_test(transactions.clone());
+        let err = engine.process_transaction(redispute.clone()).unwrap_err();
+        assert_eq!(Reason::of(&err), Reason::ClientLocked);
+
+        let policy = Policy {
+            settle_disputes_on_locked: true,
+            dispute: DisputePolicy {
+                redispute_after_chargeback: true,
+                ..DisputePolicy::default()
+            },
+            ..Policy::default()
+        };
+        let mut engine = Engine::new(policy).with_invariants();
+        for transaction in transactions {
+            engine.process_transaction(transaction).unwrap();
+        }
+        engine.process_transaction(redispute).unwrap();
+        assert_eq!(
+            *engine.clients.get(&1).unwrap(),
+            Client {
+                cid: 1,
+                available: Decimal::new(0, 0),
+                held: Decimal::new(60, 0),
+                locked: true,
+            }
+        );
+        assert_eq!(engine.transaction_state(1), Some(DisputeState::Disputed));
+
+        // Other transactions of locked client are still rejected
+        assert!(engine
+            .process_transaction(Transaction::Deposit {
+                cid: 1,
+                tx: 2,
+                amount: Decimal::new(10, 0),
+            })
+            .is_err());
+
+        engine
+            .process_transaction(Transaction::Resolve { cid: 1, tx: 1 })
+            .unwrap();
+        assert_eq!(engine.clients[&1].available, Decimal::new(60, 0));
+        assert_eq!(engine.violations(), &[]);
+        engine.check_books().unwrap();
+    }
+
+    #[test]
+    fn dispute_lifecycle() {
+        let deposit = Transaction::Deposit {
+            cid: 1,
+            tx: 1,
+            amount: Decimal::new(100, 0),
+        };
+
+        let engine = transactions_test(vec![deposit.clone()]);
+        assert_eq!(engine.transaction_state(1), Some(DisputeState::Settled));
+        assert_eq!(engine.transaction_state(2), None);
+
+        // Re-disputing after resolve is unlimited by default
+        let engine = transactions_test(vec![
+            deposit.clone(),
+            Transaction::Dispute {
+                cid: 1,
+                tx: 1,
+                amount: None,
+            },
+            Transaction::Resolve { cid: 1, tx: 1 },
+            Transaction::Dispute {
+                cid: 1,
+                tx: 1,
+                amount: None,
+            },
+            Transaction::Resolve { cid: 1, tx: 1 },
+            Transaction::Dispute {
+                cid: 1,
+                tx: 1,
+                amount: None,
+            },
+        ]);
+        assert_eq!(engine.transaction_state(1), Some(DisputeState::Disputed));
+        assert_eq!(engine.clients.get(&1).unwrap().held, Decimal::new(100, 0));
+
+        let policy = Policy {
+            settle_disputes_on_locked: true,
+            dispute: DisputePolicy {
+                redispute_after_chargeback: false,
+                max_redisputes: Some(1),
+                ..DisputePolicy::default()
+            },
+            ..Policy::default()
+        };
+        let engine = transactions_test_with(
+            vec![
+                deposit.clone(),
+                Transaction::Dispute {
+                    cid: 1,
+                    tx: 1,
+                    amount: None,
+                },
+                Transaction::Resolve { cid: 1, tx: 1 },
+                Transaction::Dispute {
+                    cid: 1,
+                    tx: 1,
+                    amount: None,
+                },
+                Transaction::Resolve { cid: 1, tx: 1 },
+                Transaction::Dispute {
+                    cid: 1,
+                    tx: 1,
+                    amount: None,
+                },
+            ],
+            policy,
+        );
+        assert_eq!(engine.transaction_state(1), Some(DisputeState::Resolved));
+        assert_eq!(engine.clients.get(&1).unwrap().held, Decimal::new(0, 0));
+
+        // Charged back transaction can't be resolved nor charged back again
+        let engine = transactions_test_with(
+            vec![
+                deposit,
+                Transaction::Dispute {
+                    cid: 1,
+                    tx: 1,
+                    amount: None,
+                },
+                Transaction::Chargeback { cid: 1, tx: 1 },
+                Transaction::Resolve { cid: 1, tx: 1 },
+                Transaction::Chargeback { cid: 1, tx: 1 },
+            ],
+            Policy {
+                settle_disputes_on_locked: true,
+                ..Policy::default()
+            },
+        );
+        assert_eq!(engine.transaction_state(1), Some(DisputeState::ChargedBack));
+        assert_eq!(
+            *engine.clients.get(&1).unwrap(),
+            Client {
+                cid: 1,
+                available: Decimal::new(0, 0),
+                held: Decimal::new(0, 0),
+                locked: true,
+            }
+        );
+    }
+
+    #[test]
+    fn dispute_partial() {
+        let transactions = vec![
+            Transaction::Deposit {
+                cid: 1,
+                tx: 1,
+                amount: Decimal::new(100, 0),
+            },
+            Transaction::Dispute {
+                cid: 1,
+                tx: 1,
+                amount: Some(Decimal::new(30, 0)),
+            },
+        ];
+
+        let engine = transactions_test(transactions.clone());
+        assert_eq!(
+            *engine.clients.get(&1).unwrap(),
+            Client {
+                cid: 1,
+                available: Decimal::new(70, 0),
+                held: Decimal::new(30, 0),
+                locked: false,
+            }
+        );
+        assert_eq!(
+            engine.history.get(&1).unwrap().undisputed,
+            Decimal::new(70, 0)
+        );
+
+        // Resolve releases only disputed part, and whole amount can be disputed again
+        let mut resolved = transactions.clone();
+        resolved.push(Transaction::Resolve { cid: 1, tx: 1 });
+        resolved.push(Transaction::Dispute {
+            cid: 1,
+            tx: 1,
+            amount: None,
+        });
+        let engine = transactions_test(resolved);
+        assert_eq!(engine.clients.get(&1).unwrap().held, Decimal::new(100, 0));
+
+        // Chargeback removes only disputed part, which can't be disputed anymore
+        let mut charged_back = transactions;
+        charged_back.push(Transaction::Chargeback { cid: 1, tx: 1 });
+        let engine = transactions_test(charged_back);
+        assert_eq!(
+            *engine.clients.get(&1).unwrap(),
+            Client {
+                cid: 1,
+                available: Decimal::new(70, 0),
+                held: Decimal::new(0, 0),
+                locked: true,
+            }
+        );
+        assert_eq!(
+            engine.history.get(&1).unwrap().undisputed,
+            Decimal::new(70, 0)
+        );
+    }
+
+    #[test]
+    fn dispute_partial_invalid() {
+        let deposit = Transaction::Deposit {
+            cid: 1,
+            tx: 1,
+            amount: Decimal::new(100, 0),
+        };
+
+        for amount in [
+            Decimal::new(0, 0),
+            Decimal::new(-10, 0),
+            Decimal::new(100, 1),
+        ] {
+            let engine = transactions_test(vec![
+                deposit.clone(),
+                Transaction::Dispute {
+                    cid: 1,
+                    tx: 1,
+                    amount: Some(amount),
+                },
+            ]);
+            assert_eq!(engine.clients.get(&1).unwrap().held, Decimal::new(0, 0));
+            assert_eq!(engine.transaction_state(1), Some(DisputeState::Settled));
+        }
+    }
+
+    fn at(transaction: Transaction, day: u64) -> Record {
+        Record {
+            transaction,
+            timestamp: Some(day * DAY),
+            position: None,
+            tenant: None,
+            currency: None,
+        }
+    }
+
+    #[test]
+    fn dispute_window() {
+        let policy = Policy {
+            dispute: DisputePolicy {
+                window_days: Some(120),
+                ..DisputePolicy::default()
+            },
+            ..Policy::default()
+        };
+
+        let records = vec![
+            at(
+                Transaction::Deposit {
+                    cid: 1,
+                    tx: 1,
+                    amount: Decimal::new(100, 0),
+                },
+                0,
+            ),
+            at(
+                Transaction::Deposit {
+                    cid: 1,
+                    tx: 2,
+                    amount: Decimal::new(50, 0),
+                },
+                10,
+            ),
+            // Out of window for tx 1, but within for tx 2
+            at(
+                Transaction::Dispute {
+                    cid: 1,
+                    tx: 1,
+                    amount: None,
+                },
+                125,
+            ),
+            at(
+                Transaction::Dispute {
+                    cid: 1,
+                    tx: 2,
+                    amount: None,
+                },
+                125,
+            ),
+        ];
+
+        let engine = process(
+            records,
+            &Config {
+                policy,
+                ..Config::default()
+            },
+        );
+        assert_eq!(engine.transaction_state(1), Some(DisputeState::Settled));
+        assert_eq!(engine.transaction_state(2), Some(DisputeState::Disputed));
+        assert_eq!(engine.clients.get(&1).unwrap().held, Decimal::new(50, 0));
+    }
+
+    #[test]
+    fn dispute_auto_resolve() {
+        let policy = Policy {
+            dispute: DisputePolicy {
+                auto_resolve_days: Some(30),
+                ..DisputePolicy::default()
+            },
+            ..Policy::default()
+        };
+
+        let records = vec![
+            at(
+                Transaction::Deposit {
+                    cid: 1,
+                    tx: 1,
+                    amount: Decimal::new(100, 0),
+                },
+                0,
+            ),
+            at(
+                Transaction::Deposit {
+                    cid: 1,
+                    tx: 2,
+                    amount: Decimal::new(50, 0),
+                },
+                0,
+            ),
+            at(
+                Transaction::Dispute {
+                    cid: 1,
+                    tx: 1,
+                    amount: None,
+                },
+                1,
+            ),
+            at(
+                Transaction::Dispute {
+                    cid: 1,
+                    tx: 2,
+                    amount: None,
+                },
+                20,
+            ),
+            at(
+                Transaction::Deposit {
+                    cid: 2,
+                    tx: 3,
+                    amount: Decimal::new(10, 0),
+                },
+                40,
+            ),
+        ];
+
+        let engine = process(
+            records,
+            &Config {
+                policy,
+                ..Config::default()
+            },
+        );
+        assert_eq!(engine.transaction_state(1), Some(DisputeState::Resolved));
+        assert_eq!(engine.transaction_state(2), Some(DisputeState::Disputed));
+        assert_eq!(
+            *engine.clients.get(&1).unwrap(),
+            Client {
+                cid: 1,
+                available: Decimal::new(100, 0),
+                held: Decimal::new(50, 0),
+                locked: false,
+            }
+        );
+    }
+
+    #[test]
+    fn chargeback_reversal() {
+        let transactions = vec![
+            Transaction::Deposit {
+                cid: 1,
+                tx: 1,
+                amount: Decimal::new(100, 0),
+            },
+            Transaction::Deposit {
+                cid: 1,
+                tx: 2,
+                amount: Decimal::new(50, 0),
+            },
+            Transaction::Dispute {
+                cid: 1,
+                tx: 1,
+                amount: Some(Decimal::new(40, 0)),
+            },
+            Transaction::Chargeback { cid: 1, tx: 1 },
+            Transaction::ChargebackReversal { cid: 1, tx: 1 },
+        ];
+
+        let engine = transactions_test(transactions.clone());
+        assert_eq!(engine.transaction_state(1), Some(DisputeState::Reversed));
+        assert_eq!(
+            *engine.clients.get(&1).unwrap(),
+            Client {
+                cid: 1,
+                available: Decimal::new(150, 0),
+                held: Decimal::new(0, 0),
+                locked: true,
+            }
+        );
+
+        let policy = Policy {
+            unlock_on_reversal: true,
+            ..Policy::default()
+        };
+        let mut reversed_twice = transactions.clone();
+        reversed_twice.push(Transaction::ChargebackReversal { cid: 1, tx: 1 });
+        let engine = transactions_test_with(reversed_twice, policy.clone());
+        assert_eq!(
+            *engine.clients.get(&1).unwrap(),
+            Client {
+                cid: 1,
+                available: Decimal::new(150, 0),
+                held: Decimal::new(0, 0),
+                locked: false,
+            }
+        );
+
+        // Client stays locked while there is other chargeback not reversed
+        let mut transactions = transactions;
+        transactions.insert(
+            3,
+            Transaction::Dispute {
+                cid: 1,
+                tx: 2,
+                amount: None,
+            },
+        );
+        transactions.insert(5, Transaction::Chargeback { cid: 1, tx: 2 });
+        let engine = transactions_test_with(
+            transactions,
+            Policy {
+                settle_disputes_on_locked: true,
+                ..policy
+            },
+        );
+        assert_eq!(
+            *engine.clients.get(&1).unwrap(),
+            Client {
+                cid: 1,
+                available: Decimal::new(100, 0),
+                held: Decimal::new(0, 0),
+                locked: true,
+            }
+        );
+    }
+
+    #[test]
+    fn books_balanced() {
+        let transactions = vec![
+            Transaction::Deposit {
+                cid: 1,
+                tx: 1,
+                amount: Decimal::new(100, 0),
+            },
+            Transaction::Withdrawal {
+                cid: 1,
+                tx: 2,
+                amount: Decimal::new(30, 0),
+            },
+            Transaction::Deposit {
+                cid: 2,
+                tx: 3,
+                amount: Decimal::new(20, 0),
+            },
+            Transaction::Dispute {
+                cid: 1,
+                tx: 1,
+                amount: Some(Decimal::new(50, 0)),
+            },
+            Transaction::Dispute {
+                cid: 2,
+                tx: 3,
+                amount: None,
+            },
+            Transaction::Chargeback { cid: 1, tx: 1 },
+        ];
+
+        let engine = transactions_test(transactions);
+        engine.check_books().unwrap();
+        assert_eq!(
+            engine.trial_balance(),
+            vec![
+                AccountBalance {
+                    account: Account::Available(1),
+                    currency: None,
+                    balance: Decimal::new(20, 0).into(),
+                },
+                AccountBalance {
+                    account: Account::Available(2),
+                    currency: None,
+                    balance: Decimal::new(0, 0).into(),
+                },
+                AccountBalance {
+                    account: Account::DisputeSuspense(1),
+                    currency: None,
+                    balance: Decimal::new(0, 0).into(),
+                },
+                AccountBalance {
+                    account: Account::DisputeSuspense(2),
+                    currency: None,
+                    balance: Decimal::new(20, 0).into(),
+                },
+                AccountBalance {
+                    account: Account::CashIn,
+                    currency: None,
+                    balance: Decimal::new(-120, 0).into(),
+                },
+                AccountBalance {
+                    account: Account::CashOut,
+                    currency: None,
+                    balance: Decimal::new(30, 0).into(),
+                },
+                AccountBalance {
+                    account: Account::ChargebackLoss,
+                    currency: None,
+                    balance: Decimal::new(50, 0).into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn books_overflow() {
+        let amount = Decimal::new(900_000_000_000_000, 0);
+        let transactions = vec![
+            Transaction::Deposit {
+                cid: 1,
+                tx: 1,
+                amount,
+            },
+            Transaction::Deposit {
+                cid: 2,
+                tx: 2,
+                amount,
+            },
+            Transaction::Deposit {
+                cid: 1,
+                tx: 3,
+                amount,
+            },
+        ];
+
+        let mut engine = Engine::new(Policy::default());
+        let reasons: Vec<_> = transactions
+            .into_iter()
+            .map(|transaction| {
+                engine
+                    .process_transaction(transaction)
+                    .map_err(|err| Reason::of(&err))
+            })
+            .collect();
+        assert!(reasons[0].is_ok());
+        assert!(reasons[1].is_ok());
+        assert_eq!(reasons[2].as_ref().unwrap_err(), &Reason::Overflow);
+
+        engine.check_books().unwrap();
+        assert_eq!(engine.clients[&1].available, amount);
+        assert_eq!(
+            engine.ledger.balance(&None, Account::CashIn).to_string(),
+            "-1800000000000000.0"
+        );
+    }
+
+    #[test]
+    fn fees() {
+        let fees: Fees = toml::from_str(
+            r#"
+[deposit]
+type = "flat"
+amount = "1"
+
+[withdrawal]
+type = "percentage"
+rate = "0.1"
+"#,
+        )
+        .unwrap();
+
+        let transactions = vec![
+            Transaction::Deposit {
+                cid: 1,
+                tx: 1,
+                amount: Decimal::new(100, 0),
+            },
+            // Fee doesn't fit in available funds
+            Transaction::Withdrawal {
+                cid: 1,
+                tx: 2,
+                amount: Decimal::new(95, 0),
+            },
+            Transaction::Withdrawal {
+                cid: 1,
+                tx: 3,
+                amount: Decimal::new(50, 0),
+            },
+            // Deposit doesn't cover fee
+            Transaction::Deposit {
+                cid: 1,
+                tx: 4,
+                amount: Decimal::new(0, 5000),
+            },
+        ];
+
+        let mut engine = Engine::new(Policy::default()).with_fees(fees);
+        for transaction in transactions {
+            engine.process_transaction(transaction).ok();
+        }
+
+        assert_eq!(
+            *engine.clients.get(&1).unwrap(),
+            Client {
+                cid: 1,
+                available: Decimal::new(44, 0),
+                held: Decimal::new(0, 0),
+                locked: false,
+            }
+        );
+        assert_eq!(engine.transaction_fee(1), Some(Decimal::new(1, 0)));
+        assert_eq!(engine.transaction_fee(2), None);
+        assert_eq!(engine.transaction_fee(3), Some(Decimal::new(5, 0)));
+        assert_eq!(
+            engine.ledger.balance(&None, Account::House),
+            Decimal::new(6, 0).into()
+        );
+        engine.check_books().unwrap();
+    }
+
+    #[test]
+    fn interest() {
+        let interest = Interest {
+            annual_rate: "0.1".parse().unwrap(),
+            day_count: Default::default(),
+            rounding: Default::default(),
+        };
+        let period = Period {
+            from: 0,
+            to: 73 * DAY,
+        };
+
+        let transactions = vec![
+            Transaction::Deposit {
+                cid: 1,
+                tx: 1,
+                amount: Decimal::new(100, 0),
+            },
+            Transaction::Deposit {
+                cid: 2,
+                tx: 2,
+                amount: Decimal::new(50, 0),
+            },
+            Transaction::Deposit {
+                cid: 2,
+                tx: 5,
+                amount: Decimal::new(50, 0),
+            },
+            Transaction::Dispute {
+                cid: 2,
+                tx: 5,
+                amount: None,
+            },
+            Transaction::Chargeback { cid: 2, tx: 5 },
+            Transaction::Deposit {
+                cid: 3,
+                tx: 3,
+                amount: Decimal::new(200, 0),
+            },
+            Transaction::Withdrawal {
+                cid: 3,
+                tx: 4,
+                amount: Decimal::new(200, 0),
+            },
+        ];
+
+        let mut engine = Engine::new(Policy::default()).with_invariants();
+        for transaction in transactions.clone() {
+            engine.process_transaction(transaction).ok();
+        }
+
+        // 100 * 0.1 * 73 / 365 = 2; locked and empty clients are skipped
+        assert_eq!(
+            engine.accrue_interest(&interest, period).unwrap(),
+            vec![Transaction::Interest {
+                cid: 1,
+                tx: 6,
+                amount: Decimal::new(2, 0),
+            }]
+        );
+        assert_eq!(engine.clients[&1].available, Decimal::new(102, 0));
+        assert_eq!(
+            engine.ledger.balance(&None, Account::InterestExpense),
+            Decimal::new(-2, 0).into()
+        );
+        assert_eq!(engine.now(), Some(73 * DAY));
+
+        // Interest is not a deposit
+        assert!(engine
+            .process_transaction(Transaction::Dispute {
+                cid: 1,
+                tx: 6,
+                amount: None,
+            })
+            .is_err());
+        assert_eq!(engine.violations(), &[]);
+        engine.check_books().unwrap();
+
+        let mut engine = Engine::new(Policy {
+            accrue_interest_on_locked: true,
+            ..Default::default()
+        })
+        .with_invariants();
+        for transaction in transactions {
+            engine.process_transaction(transaction).ok();
+        }
+
+        let accrued: Vec<_> = engine
+            .accrue_interest(&interest, period)
+            .unwrap()
+            .iter()
+            .map(|transaction| (transaction.cid(), transaction.amount()))
+            .collect();
+        assert_eq!(
+            accrued,
+            vec![(1, Some(Decimal::new(2, 0))), (2, Some(Decimal::new(1, 0)))]
+        );
+        assert_eq!(engine.violations(), &[]);
+
+        // Interest takes the last tx available
+        let mut engine = transactions_test(vec![Transaction::Deposit {
+            cid: 1,
+            tx: u64::MAX - 1,
+            amount: Decimal::new(100, 0),
+        }]);
+        let accrued = engine.accrue_interest(&interest, period).unwrap();
+        assert_eq!(accrued[0].tx(), u64::MAX);
+
+        // Interest which would overflow is rejected, the others are still accrued
+        let mut engine = transactions_test(vec![
+            Transaction::Deposit {
+                cid: 1,
+                tx: 1,
+                amount: Decimal::new(900_000_000_000_000, 0),
+            },
+            Transaction::Deposit {
+                cid: 2,
+                tx: 2,
+                amount: Decimal::new(100, 0),
+            },
+        ]);
+        let century = Period {
+            from: 0,
+            to: 36_500 * DAY,
+        };
+        let accrued: Vec<_> = engine
+            .accrue_interest(&interest, century)
+            .unwrap()
+            .iter()
+            .map(|transaction| (transaction.cid(), transaction.amount()))
+            .collect();
+        assert_eq!(accrued, vec![(2, Some(Decimal::new(1000, 0)))]);
+        engine.check_books().unwrap();
+
+        // No tx left at all, nothing is accrued
+        let mut engine = transactions_test(vec![
+            Transaction::Deposit {
+                cid: 1,
+                tx: u64::MAX,
+                amount: Decimal::new(100, 0),
+            },
+            Transaction::Deposit {
+                cid: 2,
+                tx: 5,
+                amount: Decimal::new(100, 0),
+            },
+        ]);
+        assert!(engine.accrue_interest(&interest, period).is_err());
+        assert_eq!(engine.clients[&1].available, Decimal::new(100, 0));
+        assert_eq!(engine.clients[&2].available, Decimal::new(100, 0));
+    }
+
+    #[test]
+    fn rules() {
+        let mut engine = Engine::new(Policy::default()).with_rules(Rules {
+            max_withdrawal: Some(Decimal::new(10, 0)),
+            ..Rules::default()
+        });
+
+        engine
+            .process_transaction(Transaction::Deposit {
+                cid: 1,
+                tx: 1,
+                amount: Decimal::new(100, 0),
+            })
+            .unwrap();
+        let err = engine
+            .process_transaction(Transaction::Withdrawal {
+                cid: 1,
+                tx: 2,
+                amount: Decimal::new(20, 0),
+            })
+            .unwrap_err();
+        assert!(err.to_string().starts_with("Rule max_withdrawal violated"));
+
+        // Rejected withdrawal doesn't take tx
+        engine
+            .process_transaction(Transaction::Withdrawal {
+                cid: 1,
+                tx: 2,
+                amount: Decimal::new(10, 0),
+            })
+            .unwrap();
+
+        assert_eq!(engine.clients[&1].available, Decimal::new(90, 0));
+        assert_eq!(engine.rule_violations(), vec![(Rule::MaxWithdrawal, 1)]);
+    }
+
+    #[test]
+    fn duplicates() {
+        let deposit = |cid, amount| Transaction::Deposit {
+            cid,
+            tx: 1,
+            amount: Decimal::new(amount, 0),
+        };
+        let withdrawal = |cid, amount| Transaction::Withdrawal {
+            cid,
+            tx: 2,
+            amount: Decimal::new(amount, 0),
+        };
+
+        let mut engine = Engine::new(Policy::default()).with_invariants();
+        let mut process = |transaction| {
+            engine
+                .process_transaction(transaction)
+                .map_err(|err| Reason::of(&err))
+        };
+
+        assert_eq!(process(deposit(1, 100)), Ok(Outcome::Applied));
+        assert_eq!(process(deposit(1, 100)), Ok(Outcome::Duplicate));
+        assert_eq!(process(withdrawal(1, 30)), Ok(Outcome::Applied));
+        assert_eq!(process(withdrawal(1, 30)), Ok(Outcome::Duplicate));
+
+        // Same tx, but not the same transaction
+        assert_eq!(process(deposit(1, 50)), Err(Reason::DuplicateTx));
+        assert_eq!(process(deposit(2, 100)), Err(Reason::DuplicateTx));
+        assert_eq!(process(withdrawal(1, 20)), Err(Reason::DuplicateTx));
+        assert_eq!(
+            process(Transaction::Deposit {
+                cid: 1,
+                tx: 2,
+                amount: Decimal::new(30, 0),
+            }),
+            Err(Reason::DuplicateTx)
+        );
+
+        assert_eq!(engine.clients[&1].available, Decimal::new(70, 0));
+        assert!(!engine.clients.contains_key(&2));
+        assert_eq!(engine.violations(), &[]);
+        engine.check_books().unwrap();
+    }
+
+    #[test]
+    fn convert() {
+        let rates = br#"pair, rate, timestamp
+USD/EUR, 0.9, 0
+EUR/USD, 1.1, 0
+EUR/USD, 1.2, 864000"#;
+        let fx = Fx {
+            base: Some("USD".to_owned()),
+            spread: "0.01".parse().unwrap(),
+            rates: crate::fx::read_rates(&rates[..]).unwrap(),
+        };
+        let mut engine = Engine::new(Policy::default()).with_fx(fx).with_invariants();
+        let eur = || Some("EUR".into());
+        let mut process = |record| {
+            engine
+                .process_record(record)
+                .map_err(|err| Reason::of(&err))
+        };
+        let in_eur = |transaction, day| Record {
+            currency: eur(),
+            ..at(transaction, day)
+        };
+        let convert = |tx, amount: &str, to| Transaction::Convert {
+            cid: 1,
+            tx,
+            amount: amount.parse().unwrap(),
+            to,
+        };
+        let withdrawal = |tx, amount| Transaction::Withdrawal {
+            cid: 1,
+            tx,
+            amount: Decimal::new(amount, 0),
+        };
+
+        let deposit = Transaction::Deposit {
+            cid: 1,
+            tx: 1,
+            amount: Decimal::new(100, 0),
+        };
+        assert_eq!(process(at(deposit, 1)), Ok(Outcome::Applied));
+        // 50 * 0.9 = 45, 1% spread is 0.45
+        assert_eq!(
+            process(at(convert(2, "50", eur()), 1)),
+            Ok(Outcome::Applied)
+        );
+        assert_eq!(process(in_eur(withdrawal(3, 40), 2)), Ok(Outcome::Applied));
+        assert_eq!(
+            process(in_eur(withdrawal(4, 10), 2)),
+            Err(Reason::InsufficientFunds)
+        );
+        assert_eq!(
+            process(in_eur(convert(5, "5", None), 2)),
+            Err(Reason::InsufficientFunds)
+        );
+        // Newer rate is effective: 4.55 * 1.2 = 5.46, 1% spread is 0.0546
+        assert_eq!(
+            process(in_eur(convert(5, "4.55", None), 11)),
+            Ok(Outcome::Applied)
+        );
+        assert_eq!(
+            process(at(convert(6, "1", Some("PLN".into())), 11)),
+            Err(Reason::UnknownRate)
+        );
+        // Base currency given by name
+        assert_eq!(
+            process(Record {
+                currency: Some("USD".into()),
+                ..at(withdrawal(7, 1), 11)
+            }),
+            Ok(Outcome::Applied)
+        );
+
+        // Only base currency deposits can be disputed
+        let deposit = Transaction::Deposit {
+            cid: 1,
+            tx: 8,
+            amount: Decimal::new(10, 0),
+        };
+        assert_eq!(process(in_eur(deposit, 12)), Ok(Outcome::Applied));
+        for tx in [2, 8] {
+            let dispute = Transaction::Dispute {
+                cid: 1,
+                tx,
+                amount: None,
+            };
+            assert_eq!(process(at(dispute, 12)), Err(Reason::NotDeposit));
+        }
+
+        assert_eq!(engine.transaction_rate(2), Some("0.9".parse().unwrap()));
+        assert_eq!(engine.transaction_rate(5), Some("1.2".parse().unwrap()));
+        assert_eq!(engine.transaction_rate(1), None);
+        assert_eq!(engine.violations(), &[]);
+        engine.check_books().unwrap();
+
+        let house: Vec<_> = engine
+            .trial_balance()
+            .into_iter()
+            .filter(|balance| balance.account == Account::House)
+            .map(|balance| (balance.currency, balance.balance.to_string()))
+            .collect();
+        assert_eq!(
+            house,
+            vec![(None, "0.0546".to_owned()), (eur(), "0.45".to_owned())]
+        );
+
+        let clients: Vec<_> = engine
+            .into_currency_clients()
+            .map(|(currency, client)| (currency, client.available))
+            .collect();
+        assert_eq!(
+            clients,
+            vec![
+                (None, "54.4054".parse().unwrap()),
+                (eur(), Decimal::new(10, 0)),
+            ]
         );
     }
 }
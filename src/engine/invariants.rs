@@ -0,0 +1,240 @@
+use super::Engine;
+use crate::decimal::Decimal;
+use crate::ledger::Account;
+use crate::transaction::{ClientId, Transaction, TxId};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Engine invariant which is verified after every transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Invariant {
+    /// Client held funds are equal to sum of disputed amounts of its transactions
+    HeldMatchesDisputes,
+    /// Client total funds are equal to sum of its deposits, minus withdrawals, chargebacks and fees
+    TotalMatchesHistory,
+    /// Client balances are equal to balances of its ledger accounts
+    BalancesMatchLedger,
+    /// Nothing but settling disputes happens on locked client
+    LockedInactive,
+}
+
+/// Invariant violation detected after processing transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Violation {
+    /// Index of transaction in processing order, starting from 1
+    pub seq: u64,
+    pub tx: TxId,
+    #[serde(rename = "client")]
+    pub cid: ClientId,
+    pub invariant: Invariant,
+    pub details: String,
+}
+
+/// Verifies engine invariants after every transaction.
+///
+/// Only client affected by the transaction is verified, so checking doesn't grow with number of
+/// clients. Sums are calculated from history entries, and not tracked incrementally, so checker
+/// doesn't share possible bugs with the engine.
+#[derive(Debug, Default)]
+pub(super) struct InvariantChecker {
+    /// Number of transactions checked so far
+    seq: u64,
+    /// Transactions of every client with own tx, so its history can be found without scanning
+    /// whole history
+    transactions: HashMap<ClientId, Vec<TxId>>,
+    violations: Vec<Violation>,
+}
+
+impl InvariantChecker {
+    /// Checks invariants after transaction was processed
+    pub(super) fn check(
+        &mut self,
+        engine: &Engine,
+        transaction: &Transaction,
+        was_locked: bool,
+        accepted: bool,
+    ) {
+        self.seq += 1;
+        let (cid, tx) = (transaction.cid(), transaction.tx());
+
+        if accepted {
+            if let Transaction::Deposit { .. }
+            | Transaction::Withdrawal { .. }
+            | Transaction::Interest { .. }
+            | Transaction::Convert { .. } = transaction
+            {
+                self.transactions.entry(cid).or_default().push(tx);
+            }
+        }
+
+        let client = match engine.clients.get(&cid) {
+            Some(client) => client,
+            None => return,
+        };
+
+        let entries = self
+            .transactions
+            .get(&cid)
+            .into_iter()
+            .flatten()
+            .filter_map(|tx| engine.history.get(tx));
+        let (disputed, total) = entries.fold(
+            (Decimal::new(0, 0), Decimal::new(0, 0)),
+            |(disputed, total), entry| {
+                // Only base currency is verified, which conversion may be taken from or put into
+                let total = match entry.currency {
+                    None => total + entry.amount - entry.fee - entry.charged_back,
+                    Some(_) => total,
+                };
+                let total = match &entry.conversion {
+                    Some((None, conversion)) => total + conversion.converted,
+                    _ => total,
+                };
+                (disputed + entry.disputed, total)
+            },
+        );
+
+        if client.held != disputed {
+            self.violation(
+                transaction,
+                Invariant::HeldMatchesDisputes,
+                format!("held: {}, disputed: {}", client.held, disputed),
+            );
+        }
+
+        if client.available + client.held != total {
+            self.violation(
+                transaction,
+                Invariant::TotalMatchesHistory,
+                format!(
+                    "total: {}, from history: {}",
+                    client.available + client.held,
+                    total
+                ),
+            );
+        }
+
+        let available = engine.ledger.client_balance(&None, Account::Available(cid));
+        let held = engine
+            .ledger
+            .client_balance(&None, Account::DisputeSuspense(cid));
+        if client.available != available || client.held != held {
+            self.violation(
+                transaction,
+                Invariant::BalancesMatchLedger,
+                format!(
+                    "available: {} (ledger: {}), held: {} (ledger: {})",
+                    client.available, available, client.held, held
+                ),
+            );
+        }
+
+        let allowed_on_locked = match transaction {
+            Transaction::Resolve { .. } | Transaction::Chargeback { .. } => {
+                engine.policy.settle_disputes_on_locked
+            }
+            Transaction::ChargebackReversal { .. } => true,
+            // Only charged back transaction can be disputed on locked client
+            Transaction::Dispute { .. } => engine.policy.dispute.redispute_after_chargeback,
+            Transaction::Interest { .. } => engine.policy.accrue_interest_on_locked,
+            _ => false,
+        };
+        if was_locked && accepted && !allowed_on_locked {
+            self.violation(
+                transaction,
+                Invariant::LockedInactive,
+                "transaction accepted on locked client".to_owned(),
+            );
+        }
+    }
+
+    fn violation(&mut self, transaction: &Transaction, invariant: Invariant, details: String) {
+        self.violations.push(Violation {
+            seq: self.seq,
+            tx: transaction.tx(),
+            cid: transaction.cid(),
+            invariant,
+            details,
+        })
+    }
+
+    /// All violations found so far
+    pub(super) fn violations(&self) -> &[Violation] {
+        &self.violations
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Policy;
+
+    #[test]
+    fn no_violations() {
+        let mut engine = Engine::new(Policy::default()).with_invariants();
+
+        for transaction in [
+            Transaction::Deposit {
+                cid: 1,
+                tx: 1,
+                amount: Decimal::new(100, 0),
+            },
+            Transaction::Withdrawal {
+                cid: 1,
+                tx: 2,
+                amount: Decimal::new(30, 0),
+            },
+            Transaction::Dispute {
+                cid: 1,
+                tx: 1,
+                amount: Some(Decimal::new(50, 0)),
+            },
+            Transaction::Chargeback { cid: 1, tx: 1 },
+            // Rejected, so not a violation
+            Transaction::Deposit {
+                cid: 1,
+                tx: 3,
+                amount: Decimal::new(10, 0),
+            },
+            Transaction::ChargebackReversal { cid: 1, tx: 1 },
+        ] {
+            engine.process_transaction(transaction).ok();
+        }
+
+        assert_eq!(engine.violations(), &[]);
+    }
+
+    #[test]
+    fn violations() {
+        let mut engine = Engine::new(Policy::default()).with_invariants();
+        engine
+            .process_transaction(Transaction::Deposit {
+                cid: 1,
+                tx: 1,
+                amount: Decimal::new(100, 0),
+            })
+            .unwrap();
+
+        // Breaking engine state behind its back
+        engine.clients.get_mut(&1).unwrap().held = Decimal::new(10, 0);
+        engine.clients.get_mut(&1).unwrap().locked = true;
+        engine
+            .process_transaction(Transaction::ChargebackReversal { cid: 1, tx: 1 })
+            .ok();
+
+        let invariants: Vec<_> = engine
+            .violations()
+            .iter()
+            .map(|violation| (violation.seq, violation.invariant))
+            .collect();
+        assert_eq!(
+            invariants,
+            vec![
+                (2, Invariant::HeldMatchesDisputes),
+                (2, Invariant::TotalMatchesHistory),
+                (2, Invariant::BalancesMatchLedger),
+            ]
+        );
+    }
+}
@@ -0,0 +1,258 @@
+use super::{Engine, Outcome};
+use crate::client::Client;
+use crate::dispute::DisputeState;
+use crate::transaction::{Timestamp, Transaction};
+use anyhow::{Error, Result};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Transaction processed by engine, with state of its client around it
+#[derive(Debug, Clone, Copy)]
+pub struct Event<'a> {
+    pub transaction: &'a Transaction,
+    pub timestamp: Option<Timestamp>,
+    /// Client state before transaction, `None` if client didn't exist yet
+    pub before: Option<&'a Client>,
+    /// Client state after transaction, `None` if client still doesn't exist
+    pub after: Option<&'a Client>,
+}
+
+/// Reacts to what engine does, without touching transaction handlers.
+///
+/// Every callback does nothing by default, so only interesting ones has to be implemented.
+/// Observers are called after transaction is fully processed, in order they were registered.
+pub trait EngineObserver {
+    /// Transaction was accepted
+    fn on_accepted(&mut self, _event: &Event) {}
+
+    /// Transaction was rejected for given reason
+    fn on_rejected(&mut self, _event: &Event, _reason: &Error) {}
+
+    /// Transaction was ignored, as it is a re-submission of transaction accepted before
+    fn on_duplicate(&mut self, _event: &Event) {}
+
+    /// Client appeared for the first time - it happens even if its first transaction is rejected
+    fn on_client_created(&mut self, _client: &Client) {}
+
+    /// Client got locked by transaction
+    fn on_locked(&mut self, _event: &Event) {}
+
+    /// Dispute was opened
+    fn on_dispute_opened(&mut self, _event: &Event) {}
+
+    /// Dispute was closed, by resolve or chargeback, leaving transaction in given state
+    fn on_dispute_closed(&mut self, _event: &Event, _state: DisputeState) {}
+}
+
+/// Shared observer, so its state can be inspected after it is registered in the engine
+impl<T: EngineObserver> EngineObserver for Rc<RefCell<T>> {
+    fn on_accepted(&mut self, event: &Event) {
+        self.borrow_mut().on_accepted(event)
+    }
+
+    fn on_rejected(&mut self, event: &Event, reason: &Error) {
+        self.borrow_mut().on_rejected(event, reason)
+    }
+
+    fn on_duplicate(&mut self, event: &Event) {
+        self.borrow_mut().on_duplicate(event)
+    }
+
+    fn on_client_created(&mut self, client: &Client) {
+        self.borrow_mut().on_client_created(client)
+    }
+
+    fn on_locked(&mut self, event: &Event) {
+        self.borrow_mut().on_locked(event)
+    }
+
+    fn on_dispute_opened(&mut self, event: &Event) {
+        self.borrow_mut().on_dispute_opened(event)
+    }
+
+    fn on_dispute_closed(&mut self, event: &Event, state: DisputeState) {
+        self.borrow_mut().on_dispute_closed(event, state)
+    }
+}
+
+/// Observers registered in the engine
+#[derive(Default)]
+pub(super) struct Observers(Vec<Box<dyn EngineObserver>>);
+
+impl std::fmt::Debug for Observers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Observers({})", self.0.len())
+    }
+}
+
+impl Observers {
+    pub(super) fn add(&mut self, observer: Box<dyn EngineObserver>) {
+        self.0.push(observer)
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Notifies all observers about processed transaction
+    pub(super) fn notify(
+        &mut self,
+        engine: &Engine,
+        transaction: &Transaction,
+        timestamp: Option<Timestamp>,
+        before: Option<&Client>,
+        result: &Result<Outcome>,
+    ) {
+        let after = engine.client_state(transaction.cid());
+        let event = Event {
+            transaction,
+            timestamp,
+            before,
+            after,
+        };
+        let closed = match transaction {
+            Transaction::Resolve { tx, .. } | Transaction::Chargeback { tx, .. } => {
+                engine.transaction_state(*tx)
+            }
+            _ => None,
+        };
+        let locked = !before.is_some_and(|client| client.locked)
+            && after.is_some_and(|client| client.locked);
+
+        for observer in &mut self.0 {
+            if let (None, Some(client)) = (before, after) {
+                observer.on_client_created(client);
+            }
+
+            match result {
+                Err(reason) => observer.on_rejected(&event, reason),
+                Ok(Outcome::Duplicate) => observer.on_duplicate(&event),
+                Ok(Outcome::Applied) => {
+                    observer.on_accepted(&event);
+
+                    match (transaction, closed) {
+                        (Transaction::Dispute { .. }, _) => observer.on_dispute_opened(&event),
+                        (
+                            Transaction::Resolve { .. } | Transaction::Chargeback { .. },
+                            Some(state),
+                        ) => observer.on_dispute_closed(&event, state),
+                        _ => (),
+                    }
+
+                    if locked {
+                        observer.on_locked(&event);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Policy;
+    use crate::decimal::Decimal;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Records every callback as string
+    struct Recorder(&'static str, Rc<RefCell<Vec<String>>>);
+
+    impl Recorder {
+        fn record(&self, event: String) {
+            self.1.borrow_mut().push(format!("{}: {}", self.0, event))
+        }
+    }
+
+    impl EngineObserver for Recorder {
+        fn on_accepted(&mut self, event: &Event) {
+            let after = event.after.unwrap();
+            self.record(format!(
+                "accepted {:?} {}, available: {}",
+                event.transaction.ttype(),
+                event.transaction.tx(),
+                after.available
+            ))
+        }
+
+        fn on_rejected(&mut self, event: &Event, reason: &Error) {
+            self.record(format!("rejected {}, {}", event.transaction.tx(), reason))
+        }
+
+        fn on_client_created(&mut self, client: &Client) {
+            self.record(format!("created {}", client.cid))
+        }
+
+        fn on_locked(&mut self, event: &Event) {
+            self.record(format!("locked {}", event.transaction.cid()))
+        }
+
+        fn on_dispute_opened(&mut self, event: &Event) {
+            self.record(format!("opened {}", event.transaction.tx()))
+        }
+
+        fn on_dispute_closed(&mut self, event: &Event, state: DisputeState) {
+            self.record(format!("closed {} {:?}", event.transaction.tx(), state))
+        }
+    }
+
+    #[test]
+    fn observers() {
+        let events = Rc::new(RefCell::new(vec![]));
+        let mut engine = Engine::new(Policy::default())
+            .with_observer(Recorder("a", events.clone()))
+            .with_observer(Recorder("b", events.clone()));
+
+        for transaction in [
+            Transaction::Deposit {
+                cid: 1,
+                tx: 1,
+                amount: Decimal::new(10, 0),
+            },
+            Transaction::Dispute {
+                cid: 1,
+                tx: 1,
+                amount: None,
+            },
+            Transaction::Chargeback { cid: 1, tx: 1 },
+            Transaction::Deposit {
+                cid: 1,
+                tx: 2,
+                amount: Decimal::new(10, 0),
+            },
+        ] {
+            engine.process_transaction(transaction).ok();
+        }
+
+        let events = events.borrow();
+        let a: Vec<_> = events
+            .iter()
+            .filter_map(|event| event.strip_prefix("a: "))
+            .collect();
+        assert_eq!(
+            a,
+            vec![
+                "created 1",
+                "accepted Deposit 1, available: 10.0",
+                "accepted Dispute 1, available: 0.0",
+                "opened 1",
+                "accepted Chargeback 1, available: 0.0",
+                "closed 1 ChargedBack",
+                "locked 1",
+                "rejected 2, Client is locked, client id: 1",
+            ]
+        );
+        // Both observers get every event, in order of registration
+        assert_eq!(events.len(), 2 * a.len());
+        assert_eq!(
+            events[..4],
+            [
+                "a: created 1",
+                "a: accepted Deposit 1, available: 10.0",
+                "b: created 1",
+                "b: accepted Deposit 1, available: 10.0",
+            ]
+        );
+    }
+}
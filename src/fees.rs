@@ -0,0 +1,156 @@
+use crate::decimal::{Decimal, Rounding};
+use crate::transaction::ClientId;
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+
+/// Single fee definition
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Fee {
+    /// Same fee for every transaction
+    Flat { amount: Decimal },
+    /// Fee being part of transaction amount, optionally clamped
+    Percentage {
+        /// Fee rate, eg. `0.01` for 1%
+        rate: Decimal,
+        min: Option<Decimal>,
+        max: Option<Decimal>,
+    },
+    /// Fee depending on transaction amount
+    Tiered { tiers: Vec<Tier> },
+}
+
+/// Fee tier
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tier {
+    /// Biggest transaction amount this tier applies to, no limit if not given
+    pub up_to: Option<Decimal>,
+    pub fee: Fee,
+}
+
+impl Fee {
+    /// Calculates fee for transaction with given amount.
+    ///
+    /// Percentage fees are rounded half away from zero. Tiered fee uses first tier covering the
+    /// amount, and applies it to whole amount (not only to part over previous tier). If no tier
+    /// covers amount, there is no fee.
+    pub fn calculate(&self, amount: Decimal) -> Decimal {
+        match self {
+            Self::Flat { amount } => *amount,
+            Self::Percentage { rate, min, max } => {
+                let mut fee = amount.mul_round(*rate, Rounding::HalfUp);
+                if let Some(min) = min {
+                    fee = fee.max(*min);
+                }
+                if let Some(max) = max {
+                    fee = fee.min(*max);
+                }
+                fee
+            }
+            Self::Tiered { tiers } => tiers
+                .iter()
+                .find(|tier| tier.up_to.is_none_or(|up_to| amount <= up_to))
+                .map_or(Decimal::new(0, 0), |tier| tier.fee.calculate(amount)),
+        }
+    }
+}
+
+/// Fees for every transaction type which can have one
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeeSchedule {
+    pub deposit: Option<Fee>,
+    pub withdrawal: Option<Fee>,
+}
+
+/// Fees configuration - default schedule, with per-client overrides
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Fees {
+    pub deposit: Option<Fee>,
+    pub withdrawal: Option<Fee>,
+    /// Client specific schedules. Fee given for transaction type overrides the default one.
+    #[serde(deserialize_with = "deserialize_clients")]
+    pub clients: HashMap<ClientId, FeeSchedule>,
+}
+
+impl Fees {
+    /// Calculates fee of client deposit
+    pub fn deposit(&self, cid: ClientId, amount: Decimal) -> Decimal {
+        let fee = self
+            .clients
+            .get(&cid)
+            .and_then(|schedule| schedule.deposit.as_ref())
+            .or(self.deposit.as_ref());
+        fee.map_or(Decimal::new(0, 0), |fee| fee.calculate(amount))
+    }
+
+    /// Calculates fee of client withdrawal
+    pub fn withdrawal(&self, cid: ClientId, amount: Decimal) -> Decimal {
+        let fee = self
+            .clients
+            .get(&cid)
+            .and_then(|schedule| schedule.withdrawal.as_ref())
+            .or(self.withdrawal.as_ref());
+        fee.map_or(Decimal::new(0, 0), |fee| fee.calculate(amount))
+    }
+}
+
+/// Client ids are TOML keys, which are always strings
+fn deserialize_clients<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<ClientId, FeeSchedule>, D::Error> {
+    HashMap::<String, FeeSchedule>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(cid, schedule)| {
+            cid.parse()
+                .map(|cid| (cid, schedule))
+                .map_err(serde::de::Error::custom)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::Fees;
+    use crate::decimal::Decimal;
+
+    #[test]
+    fn fees() {
+        let fees: Fees = toml::from_str(
+            r#"
+[deposit]
+type = "flat"
+amount = "0.5"
+
+[withdrawal]
+type = "tiered"
+tiers = [
+    { up_to = "100", fee = { type = "flat", amount = "1" } },
+    { up_to = "1000", fee = { type = "percentage", rate = "0.01", max = "5" } },
+    { fee = { type = "percentage", rate = "0.001", min = "5" } },
+]
+
+[clients.42.withdrawal]
+type = "flat"
+amount = "0"
+"#,
+        )
+        .unwrap();
+
+        let amount = |s: &str| s.parse::<Decimal>().unwrap();
+
+        assert_eq!(fees.deposit(1, amount("10")), amount("0.5"));
+        assert_eq!(fees.deposit(42, amount("10")), amount("0.5"));
+
+        assert_eq!(fees.withdrawal(1, amount("100")), amount("1"));
+        assert_eq!(fees.withdrawal(1, amount("100.01")), amount("1.0001"));
+        assert_eq!(fees.withdrawal(1, amount("999")), amount("5"));
+        assert_eq!(fees.withdrawal(1, amount("2000")), amount("5"));
+        assert_eq!(fees.withdrawal(1, amount("8000")), amount("8"));
+        assert_eq!(fees.withdrawal(42, amount("8000")), amount("0"));
+
+        assert_eq!(Fees::default().withdrawal(1, amount("10")), amount("0"));
+    }
+}
@@ -0,0 +1,309 @@
+use crate::decimal::{Decimal, Rounding};
+use crate::rejection::{reject, Reason};
+use crate::transaction::{Currency, Timestamp};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Currency conversion settings
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Fx {
+    /// Name of the base currency (the one of rows without currency) in rates file. Base currency
+    /// cannot be converted if not set.
+    pub base: Option<String>,
+
+    /// Part of converted amount kept by the house, eg. `0.01` for 1%
+    #[serde(deserialize_with = "deserialize_spread")]
+    pub spread: Decimal,
+
+    /// Rates of currency pairs, read from file given with `--fx-rates`
+    #[serde(skip)]
+    pub rates: Rates,
+}
+
+impl Default for Fx {
+    fn default() -> Self {
+        Self {
+            base: None,
+            spread: Decimal::new(0, 0),
+            rates: Rates::default(),
+        }
+    }
+}
+
+/// Spread is taken from converted amount, so it has to leave something to the client
+fn deserialize_spread<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Decimal, D::Error> {
+    let spread = Decimal::deserialize(deserializer)?;
+    if spread < Decimal::new(0, 0) || spread >= Decimal::new(1, 0) {
+        Err(serde::de::Error::custom(format!(
+            "Spread has to be at least 0 and less than 1, {} given",
+            spread
+        )))
+    } else {
+        Ok(spread)
+    }
+}
+
+/// Result of conversion, in the target currency
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Conversion {
+    /// Rate amount was converted with
+    pub rate: Decimal,
+    /// Amount client gets
+    pub converted: Decimal,
+    /// Part of converted amount kept by the house
+    pub spread: Decimal,
+}
+
+impl Fx {
+    /// Returns currency, `None` if it is the base currency given by name
+    pub fn normalize(&self, currency: Currency) -> Currency {
+        match (&currency, &self.base) {
+            (Some(name), Some(base)) if **name == **base => None,
+            _ => currency,
+        }
+    }
+
+    /// Returns name of currency in rates file
+    fn name<'a>(&'a self, currency: &'a Currency) -> Result<&'a str> {
+        match currency {
+            Some(currency) => Ok(currency),
+            None => self.base.as_deref().ok_or_else(|| {
+                reject!(
+                    Reason::UnknownRate,
+                    "Base currency name is not configured, so it cannot be converted"
+                )
+            }),
+        }
+    }
+
+    /// Converts amount between currencies, with rate effective at given time (the latest one if
+    /// time is not known). Amount is rounded down to 4 decimal places, and then the spread is
+    /// taken, rounded up - so house never pays for rounding.
+    pub fn convert(
+        &self,
+        amount: Decimal,
+        from: &Currency,
+        to: &Currency,
+        at: Option<Timestamp>,
+    ) -> Result<Conversion> {
+        let (from, to) = (self.name(from)?, self.name(to)?);
+        let rate = self.rates.rate(from, to, at)?;
+
+        let overflow = || {
+            reject!(
+                Reason::Overflow,
+                "Converting {} {} into {} would overflow",
+                amount,
+                from,
+                to
+            )
+        };
+        let gross = amount
+            .checked_mul_ratio(rate, 1, 1, Rounding::Floor)
+            .ok_or_else(overflow)?;
+        let spread = gross
+            .checked_mul_ratio(self.spread, 1, 1, Rounding::Ceiling)
+            .ok_or_else(overflow)?;
+        let converted = gross - spread;
+
+        if converted <= Decimal::new(0, 0) {
+            return Err(reject!(
+                Reason::InvalidAmount,
+                "Amount {} {} is too small to be converted into {}",
+                amount,
+                from,
+                to
+            ));
+        }
+
+        Ok(Conversion {
+            rate,
+            converted,
+            spread,
+        })
+    }
+}
+
+/// Single row of rates file
+#[derive(Debug, Deserialize)]
+struct RateRow {
+    /// Currencies as `<from>/<to>`, eg. `USD/EUR` - amount of EUR for 1 USD
+    pair: String,
+    rate: Decimal,
+    /// Since when rate is effective
+    timestamp: Timestamp,
+}
+
+/// Conversion rates of currency pairs. Every rate is effective since its timestamp, until the next
+/// rate of the pair.
+///
+/// Rates are never inverted - converting both ways needs both pairs, as inverted rate is not exact
+/// decimal.
+#[derive(Debug, Clone, Default)]
+pub struct Rates {
+    /// Rates of every pair, ordered by timestamp
+    pairs: HashMap<(String, String), Vec<(Timestamp, Decimal)>>,
+}
+
+impl Rates {
+    /// Returns rate of pair effective at given time, the latest one if time is not known
+    pub fn rate(&self, from: &str, to: &str, at: Option<Timestamp>) -> Result<Decimal> {
+        let rates = self
+            .pairs
+            .get(&(from.to_owned(), to.to_owned()))
+            .map_or(&[][..], Vec::as_slice);
+
+        let rate = match at {
+            Some(at) => rates.iter().rev().find(|(since, _)| *since <= at),
+            None => rates.last(),
+        };
+
+        rate.map(|(_, rate)| *rate).ok_or_else(|| match at {
+            Some(at) => reject!(
+                Reason::UnknownRate,
+                "No rate of {}/{} effective at {}",
+                from,
+                to,
+                at
+            ),
+            None => reject!(Reason::UnknownRate, "No rate of {}/{}", from, to),
+        })
+    }
+}
+
+/// Reads rates from csv with `pair`, `rate` and `timestamp` columns
+pub fn read_rates(reader: impl std::io::Read) -> Result<Rates> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    let mut rates = Rates::default();
+
+    for row in reader.deserialize() {
+        let RateRow {
+            pair,
+            rate,
+            timestamp,
+        } = row?;
+
+        let (from, to) = match pair.split_once('/') {
+            Some((from, to)) if !from.is_empty() && !to.is_empty() && from != to => (from, to),
+            _ => return Err(anyhow!("Invalid currency pair: {}", pair)),
+        };
+        if rate <= Decimal::new(0, 0) {
+            return Err(anyhow!(
+                "Rate of {} has to be positive, {} given",
+                pair,
+                rate
+            ));
+        }
+
+        let pair_rates = rates
+            .pairs
+            .entry((from.to_owned(), to.to_owned()))
+            .or_default();
+        match pair_rates.binary_search_by_key(&timestamp, |(since, _)| *since) {
+            Ok(_) => {
+                return Err(anyhow!(
+                    "Rate of {} given twice for timestamp {}",
+                    pair,
+                    timestamp
+                ))
+            }
+            Err(idx) => pair_rates.insert(idx, (timestamp, rate)),
+        }
+    }
+
+    Ok(rates)
+}
+
+/// Reads rates from file
+pub fn read_rates_file(path: &Path) -> Result<Rates> {
+    read_rates(std::fs::File::open(path)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{read_rates, Fx};
+    use crate::decimal::Decimal;
+    use crate::rejection::Reason;
+
+    #[test]
+    fn rates() {
+        let data = br#"pair, rate, timestamp
+USD/EUR, 0.9, 100
+USD/EUR, 0.95, 50
+EUR/USD, 1.1, 0"#;
+        let rates = read_rates(&data[..]).unwrap();
+
+        assert_eq!(
+            rates.rate("USD", "EUR", Some(70)).unwrap(),
+            "0.95".parse().unwrap()
+        );
+        assert_eq!(
+            rates.rate("USD", "EUR", Some(100)).unwrap(),
+            "0.9".parse().unwrap()
+        );
+        assert_eq!(
+            rates.rate("USD", "EUR", None).unwrap(),
+            "0.9".parse().unwrap()
+        );
+        assert_eq!(
+            rates.rate("EUR", "USD", Some(70)).unwrap(),
+            "1.1".parse().unwrap()
+        );
+        // Not effective yet
+        let err = rates.rate("USD", "EUR", Some(10)).unwrap_err();
+        assert_eq!(Reason::of(&err), Reason::UnknownRate);
+        // Never inverted
+        assert!(rates.rate("EUR", "PLN", None).is_err());
+
+        assert!(read_rates(&b"pair,rate,timestamp\nUSD/USD,1,0"[..]).is_err());
+        assert!(read_rates(&b"pair,rate,timestamp\nUSD,1,0"[..]).is_err());
+        assert!(read_rates(&b"pair,rate,timestamp\nUSD/EUR,0,0"[..]).is_err());
+        assert!(read_rates(&b"pair,rate,timestamp\nUSD/EUR,1,0\nUSD/EUR,2,0"[..]).is_err());
+    }
+
+    #[test]
+    fn convert() {
+        let mut fx: Fx = toml::from_str(
+            r#"
+base = "USD"
+spread = "0.01"
+"#,
+        )
+        .unwrap();
+        fx.rates = read_rates(&b"pair,rate,timestamp\nUSD/EUR,0.9123,0"[..]).unwrap();
+        let (usd, eur) = (None, Some("EUR".into()));
+
+        // 100.5 * 0.9123 = 91.68615, 1% of 91.6861 is 0.916861
+        let conversion = fx
+            .convert("100.5".parse().unwrap(), &usd, &eur, Some(10))
+            .unwrap();
+        assert_eq!(conversion.rate, "0.9123".parse().unwrap());
+        assert_eq!(conversion.converted, "90.7692".parse().unwrap());
+        assert_eq!(conversion.spread, "0.9169".parse().unwrap());
+
+        assert_eq!(fx.normalize(Some("USD".into())), None);
+        assert_eq!(fx.normalize(eur.clone()), eur);
+
+        let err = fx
+            .convert(Decimal::new(1, 0), &eur, &usd, None)
+            .unwrap_err();
+        assert_eq!(Reason::of(&err), Reason::UnknownRate);
+        let err = fx
+            .convert(Decimal::new(0, 1), &usd, &eur, None)
+            .unwrap_err();
+        assert_eq!(Reason::of(&err), Reason::InvalidAmount);
+
+        // Base currency can't be converted without its name
+        let fx = Fx { base: None, ..fx };
+        assert!(fx.convert(Decimal::new(1, 0), &usd, &eur, None).is_err());
+
+        assert!(toml::from_str::<Fx>("spread = \"1\"").is_err());
+    }
+}
@@ -0,0 +1,228 @@
+use crate::decimal::{Decimal, Rounding};
+use crate::rejection::{reject, Reason};
+use crate::transaction::{Timestamp, DAY};
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+/// Day-count convention, telling which part of the year the period is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum DayCount {
+    /// Actual number of days, over 365 days year
+    #[default]
+    #[serde(rename = "act_365")]
+    Act365,
+    /// Every month has 30 days, over 360 days year (US bond basis)
+    #[serde(rename = "30_360")]
+    Thirty360,
+}
+
+impl DayCount {
+    /// Returns period length in days and length of year, both according to convention
+    pub fn days(self, period: Period) -> (i64, i64) {
+        let (from, to) = ((period.from / DAY) as i64, (period.to / DAY) as i64);
+
+        match self {
+            Self::Act365 => (to - from, 365),
+            Self::Thirty360 => {
+                let (y1, m1, d1) = civil(from);
+                let (y2, m2, mut d2) = civil(to);
+                let d1 = d1.min(30);
+                if d1 == 30 {
+                    d2 = d2.min(30);
+                }
+
+                (360 * (y2 - y1) + 30 * (m2 - m1) + (d2 - d1), 360)
+            }
+        }
+    }
+}
+
+/// Converts days since unix epoch to `(year, month, day)` civil date
+fn civil(days: i64) -> (i64, i64, i64) {
+    // Shifted, so year starts in March and leap day is the last day of year
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+/// Period interest is accrued for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Period {
+    pub from: Timestamp,
+    pub to: Timestamp,
+}
+
+/// Parses period given as `<from>:<to>` timestamps
+impl std::str::FromStr for Period {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (from, to) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Period has to be given as <from>:<to>"))?;
+        let (from, to) = (from.trim().parse()?, to.trim().parse()?);
+
+        if from > to {
+            return Err(anyhow!("Period ends before it starts"));
+        }
+
+        Ok(Self { from, to })
+    }
+}
+
+/// Interest configuration
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Interest {
+    /// Annual interest rate, eg. `0.05` for 5%
+    pub annual_rate: Decimal,
+    #[serde(default)]
+    pub day_count: DayCount,
+    #[serde(default)]
+    pub rounding: Rounding,
+}
+
+impl Interest {
+    /// Calculates interest accrued on balance over the period. Negative balance doesn't accrue
+    /// anything. Fails if interest doesn't fit in decimal (huge balance over long period).
+    pub fn accrued(&self, balance: Decimal, period: Period) -> Result<Decimal> {
+        if balance <= Decimal::new(0, 0) {
+            return Ok(Decimal::new(0, 0));
+        }
+
+        let (days, year) = self.day_count.days(period);
+        balance
+            .checked_mul_ratio(self.annual_rate, days, year, self.rounding)
+            .ok_or_else(|| {
+                reject!(
+                    Reason::Overflow,
+                    "Interest on {} over {} days would overflow",
+                    balance,
+                    days
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{civil, DayCount, Interest, Period};
+    use crate::decimal::{Decimal, Rounding};
+    use crate::rejection::Reason;
+    use crate::transaction::DAY;
+
+    /// Midday of given date
+    fn date(year: i64, month: i64, day: i64) -> u64 {
+        // Counting days from the epoch by hand, to not use `civil` for checking itself
+        let leap = |year: i64| year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+        let month_days = [31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+
+        let mut days = (1970..year)
+            .map(|year| if leap(year) { 366 } else { 365 })
+            .sum::<i64>();
+        days += month_days[..month as usize - 1].iter().sum::<i64>();
+        if month > 2 && leap(year) {
+            days += 1;
+        }
+        days += day - 1;
+
+        days as u64 * DAY + DAY / 2
+    }
+
+    fn period(from: u64, to: u64) -> Period {
+        Period { from, to }
+    }
+
+    #[test]
+    fn civil_dates() {
+        assert_eq!(civil(0), (1970, 1, 1));
+        for (year, month, day) in [(2000, 2, 29), (2023, 12, 31), (2024, 3, 1), (2100, 2, 28)] {
+            assert_eq!(
+                civil((date(year, month, day) / DAY) as i64),
+                (year, month, day)
+            );
+        }
+    }
+
+    #[test]
+    fn day_count() {
+        let feb = period(date(2024, 2, 1), date(2024, 3, 1));
+        assert_eq!(DayCount::Act365.days(feb), (29, 365));
+        assert_eq!(DayCount::Thirty360.days(feb), (30, 360));
+
+        let year = period(date(2023, 1, 1), date(2024, 1, 1));
+        assert_eq!(DayCount::Act365.days(year), (365, 365));
+        assert_eq!(DayCount::Thirty360.days(year), (360, 360));
+
+        // End of month adjustments
+        let month_end = period(date(2024, 1, 31), date(2024, 3, 31));
+        assert_eq!(DayCount::Act365.days(month_end), (60, 365));
+        assert_eq!(DayCount::Thirty360.days(month_end), (60, 360));
+        let to_month_end = period(date(2024, 1, 15), date(2024, 1, 31));
+        assert_eq!(DayCount::Thirty360.days(to_month_end), (16, 360));
+    }
+
+    #[test]
+    fn accrued() {
+        let interest = Interest {
+            annual_rate: "0.05".parse().unwrap(),
+            day_count: DayCount::Act365,
+            rounding: Rounding::HalfEven,
+        };
+
+        let year = period(date(2023, 1, 1), date(2024, 1, 1));
+        assert_eq!(
+            interest.accrued(Decimal::new(1000, 0), year).unwrap(),
+            Decimal::new(50, 0)
+        );
+        // 1000 * 0.05 * 10 / 365 = 1.36986...
+        let days = period(date(2023, 1, 1), date(2023, 1, 11));
+        assert_eq!(
+            interest.accrued(Decimal::new(1000, 0), days).unwrap(),
+            Decimal::new(1, 3699)
+        );
+        assert_eq!(
+            interest.accrued(Decimal::new(-1000, 0), days).unwrap(),
+            Decimal::new(0, 0)
+        );
+
+        let interest = Interest {
+            day_count: DayCount::Thirty360,
+            rounding: Rounding::Floor,
+            ..interest
+        };
+        // 1000 * 0.05 * 10 / 360 = 1.38888...
+        assert_eq!(
+            interest.accrued(Decimal::new(1000, 0), days).unwrap(),
+            Decimal::new(1, 3888)
+        );
+
+        // Huge balance over long period doesn't fit
+        let century = period(date(1970, 1, 1), date(2070, 1, 1));
+        let err = interest
+            .accrued(Decimal::new(900_000_000_000_000, 0), century)
+            .unwrap_err();
+        assert_eq!(Reason::of(&err), Reason::Overflow);
+    }
+
+    #[test]
+    fn parse_period() {
+        assert_eq!("10:20".parse::<Period>().unwrap(), period(10, 20));
+        assert!("20:10".parse::<Period>().is_err());
+        assert!("10".parse::<Period>().is_err());
+    }
+}
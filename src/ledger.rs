@@ -0,0 +1,464 @@
+use crate::decimal::{Decimal, WideDecimal};
+use crate::rejection::{reject, Reason};
+use crate::transaction::{ClientId, Currency};
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+/// Single account in the ledger
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Account {
+    /// Funds available to client
+    Available(ClientId),
+    /// Funds of client held by open disputes
+    DisputeSuspense(ClientId),
+    /// Counter account for all deposits - money which came into the system
+    CashIn,
+    /// Counter account for all withdrawals - money which left the system
+    CashOut,
+    /// Funds which were taken back from clients by chargebacks
+    ChargebackLoss,
+    /// Fees collected from clients
+    House,
+    /// Interest paid to clients
+    InterestExpense,
+    /// Counter account of conversions - currencies exchanged with clients
+    Exchange,
+}
+
+impl Account {
+    /// Returns client owning account, `None` for system accounts
+    pub fn client(self) -> Option<ClientId> {
+        match self {
+            Self::Available(cid) | Self::DisputeSuspense(cid) => Some(cid),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Account {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Available(cid) => write!(f, "available:{}", cid),
+            Self::DisputeSuspense(cid) => write!(f, "dispute_suspense:{}", cid),
+            Self::CashIn => write!(f, "cash_in"),
+            Self::CashOut => write!(f, "cash_out"),
+            Self::ChargebackLoss => write!(f, "chargeback_loss"),
+            Self::House => write!(f, "house"),
+            Self::InterestExpense => write!(f, "interest_expense"),
+            Self::Exchange => write!(f, "exchange"),
+        }
+    }
+}
+
+/// Single ledger entry - change of account balance in given currency
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub account: Account,
+    pub currency: Currency,
+    pub amount: Decimal,
+}
+
+/// Double-entry ledger.
+///
+/// Every posting consists of entries summing up to zero in every currency, so money never appears
+/// or vanishes - it is always moved from one account to another. In particular deposits are moved
+/// from `CashIn`, which is why its balance is negative. Every account has separate balance in every
+/// currency.
+///
+/// System accounts sum up amounts of all clients, so balances are wide and never overflow. Client
+/// accounts are kept within `Decimal`, as client balances are.
+#[derive(Debug, Default)]
+pub struct Ledger {
+    balances: HashMap<(Currency, Account), WideDecimal>,
+}
+
+impl Ledger {
+    /// Posts balanced entries, returning error if they doesn't sum up to zero in every currency,
+    /// or if any client balance (or client total) would not fit in decimal. Nothing is changed on
+    /// error.
+    ///
+    /// Zero entries are skipped, so accounts which are never actually used (eg. `House` when
+    /// there are no fees) doesn't show up.
+    pub fn post(&mut self, entries: &[Entry]) -> Result<()> {
+        let mut sums: Vec<(&Currency, WideDecimal)> = vec![];
+        for entry in entries {
+            match sums
+                .iter_mut()
+                .find(|(currency, _)| **currency == entry.currency)
+            {
+                Some((_, sum)) => *sum += entry.amount.into(),
+                None => sums.push((&entry.currency, entry.amount.into())),
+            }
+        }
+
+        if let Some((currency, sum)) = sums
+            .into_iter()
+            .find(|(_, sum)| *sum != WideDecimal::default())
+        {
+            return Err(anyhow!(
+                "Unbalanced posting, entries{} sum up to {}",
+                currency_suffix(currency),
+                sum
+            ));
+        }
+
+        // Account may be given more than once, so balances are checked after all its entries
+        let mut updated: Vec<((Currency, Account), WideDecimal)> = vec![];
+        for entry in entries
+            .iter()
+            .filter(|entry| entry.amount != Decimal::new(0, 0))
+        {
+            let key = (entry.currency.clone(), entry.account);
+            match updated.iter_mut().find(|(updated, _)| *updated == key) {
+                Some((_, balance)) => *balance += entry.amount.into(),
+                None => {
+                    let balance =
+                        self.balance(&entry.currency, entry.account) + entry.amount.into();
+                    updated.push((key, balance));
+                }
+            }
+        }
+
+        let updated_balance = |currency: &Currency, account| {
+            updated
+                .iter()
+                .find(|((updated_currency, updated), _)| {
+                    updated_currency == currency && *updated == account
+                })
+                .map_or_else(|| self.balance(currency, account), |(_, balance)| *balance)
+        };
+        for (currency, cid) in updated
+            .iter()
+            .filter_map(|((currency, account), _)| Some((currency, account.client()?)))
+        {
+            // Client total is reported as well, so it has to fit too
+            let available = updated_balance(currency, Account::Available(cid));
+            let held = updated_balance(currency, Account::DisputeSuspense(cid));
+            for balance in [available, held, available + held] {
+                if Decimal::try_from(balance).is_err() {
+                    return Err(reject!(
+                        Reason::Overflow,
+                        "Balance of client {}{} would overflow: {}",
+                        cid,
+                        currency_suffix(currency),
+                        balance
+                    ));
+                }
+            }
+        }
+
+        self.balances.extend(updated);
+        Ok(())
+    }
+
+    /// Moves amount between two accounts, returning error if client balance would overflow
+    pub fn transfer(
+        &mut self,
+        currency: &Currency,
+        from: Account,
+        to: Account,
+        amount: Decimal,
+    ) -> Result<()> {
+        self.post(&[
+            Entry {
+                account: from,
+                currency: currency.clone(),
+                amount: -amount,
+            },
+            Entry {
+                account: to,
+                currency: currency.clone(),
+                amount,
+            },
+        ])
+    }
+
+    /// Returns balance of an account in given currency
+    pub fn balance(&self, currency: &Currency, account: Account) -> WideDecimal {
+        self.balances
+            .get(&(currency.clone(), account))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Returns balance of client account in given currency, which always fits in decimal
+    pub fn client_balance(&self, currency: &Currency, account: Account) -> Decimal {
+        Decimal::try_from(self.balance(currency, account))
+            .expect("Client balance is checked when posting")
+    }
+
+    /// Returns balances of all accounts ever used, ordered by currency (the base one first) and
+    /// account
+    pub fn balances(&self) -> Vec<AccountBalance> {
+        let mut balances: Vec<_> = self
+            .balances
+            .iter()
+            .map(|((currency, account), balance)| AccountBalance {
+                account: *account,
+                currency: currency.clone(),
+                balance: *balance,
+            })
+            .collect();
+
+        balances.sort_by(|a, b| (&a.currency, a.account).cmp(&(&b.currency, b.account)));
+        balances
+    }
+
+    /// Ensures that all accounts sum up to zero in every currency, returning error otherwise
+    pub fn ensure_balanced(&self) -> Result<()> {
+        let mut sums = HashMap::<&Currency, WideDecimal>::new();
+        for ((currency, _), balance) in &self.balances {
+            *sums.entry(currency).or_default() += *balance;
+        }
+
+        match sums
+            .into_iter()
+            .find(|(_, sum)| *sum != WideDecimal::default())
+        {
+            Some((currency, sum)) => Err(anyhow!(
+                "Trial balance{} doesn't sum up to zero: {}",
+                currency_suffix(currency),
+                sum
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Formats currency for messages, nothing for the base currency
+fn currency_suffix(currency: &Currency) -> String {
+    currency
+        .as_ref()
+        .map_or_else(String::new, |currency| format!(" in {}", currency))
+}
+
+/// Account balance entry of the trial balance. Accounts in currency other than the base one are
+/// stored with currency after account name, eg. `available:1:EUR`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(into = "OutputAccountBalance")]
+pub struct AccountBalance {
+    pub account: Account,
+    pub currency: Currency,
+    pub balance: WideDecimal,
+}
+
+/// Account balance ready to be stored in output
+#[derive(Debug, Serialize)]
+struct OutputAccountBalance {
+    account: String,
+    balance: WideDecimal,
+}
+
+impl From<AccountBalance> for OutputAccountBalance {
+    fn from(
+        AccountBalance {
+            account,
+            currency,
+            balance,
+        }: AccountBalance,
+    ) -> Self {
+        let account = match currency {
+            Some(currency) => format!("{}:{}", account, currency),
+            None => account.to_string(),
+        };
+
+        Self { account, balance }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Account, Entry, Ledger};
+    use crate::decimal::Decimal;
+    use crate::output::store_csv;
+    use crate::rejection::Reason;
+
+    #[test]
+    fn posting() {
+        let mut ledger = Ledger::default();
+
+        ledger
+            .transfer(
+                &None,
+                Account::CashIn,
+                Account::Available(1),
+                Decimal::new(10, 0),
+            )
+            .unwrap();
+        ledger
+            .transfer(
+                &None,
+                Account::Available(1),
+                Account::DisputeSuspense(1),
+                Decimal::new(4, 0),
+            )
+            .unwrap();
+        assert!(ledger
+            .post(&[
+                Entry {
+                    account: Account::Available(1),
+                    currency: None,
+                    amount: Decimal::new(-1, 0),
+                },
+                Entry {
+                    account: Account::CashOut,
+                    currency: None,
+                    amount: Decimal::new(2, 0),
+                },
+            ])
+            .is_err());
+
+        assert_eq!(
+            ledger.client_balance(&None, Account::Available(1)),
+            Decimal::new(6, 0)
+        );
+        assert_eq!(
+            ledger.client_balance(&None, Account::DisputeSuspense(1)),
+            Decimal::new(4, 0)
+        );
+        assert_eq!(
+            ledger.balance(&None, Account::CashOut),
+            Decimal::new(0, 0).into()
+        );
+        ledger.ensure_balanced().unwrap();
+
+        let mut buf = vec![];
+        store_csv(std::io::Cursor::new(&mut buf), ledger.balances()).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            r#"account,balance
+available:1,6.0
+dispute_suspense:1,4.0
+cash_in,-10.0
+"#
+        );
+    }
+
+    #[test]
+    fn overflow() {
+        let mut ledger = Ledger::default();
+        let amount = Decimal::new(900_000_000_000_000, 0);
+
+        // System account sums up all clients, so it doesn't fit in decimal
+        ledger
+            .transfer(&None, Account::CashIn, Account::Available(1), amount)
+            .unwrap();
+        ledger
+            .transfer(&None, Account::CashIn, Account::Available(2), amount)
+            .unwrap();
+        assert_eq!(
+            ledger.balance(&None, Account::CashIn).to_string(),
+            "-1800000000000000.0"
+        );
+        ledger.ensure_balanced().unwrap();
+
+        // Client balance has to fit, posting is rejected without changing anything
+        let err = ledger
+            .transfer(&None, Account::CashIn, Account::Available(1), amount)
+            .unwrap_err();
+        assert_eq!(Reason::of(&err), Reason::Overflow);
+        assert_eq!(ledger.client_balance(&None, Account::Available(1)), amount);
+        assert_eq!(
+            ledger.balance(&None, Account::CashIn).to_string(),
+            "-1800000000000000.0"
+        );
+
+        // So does client total
+        ledger
+            .transfer(
+                &None,
+                Account::Available(1),
+                Account::DisputeSuspense(1),
+                Decimal::new(100_000_000_000_000, 0),
+            )
+            .unwrap();
+        let err = ledger
+            .transfer(
+                &None,
+                Account::CashIn,
+                Account::Available(1),
+                Decimal::new(100_000_000_000_000, 0),
+            )
+            .unwrap_err();
+        assert_eq!(Reason::of(&err), Reason::Overflow);
+    }
+
+    #[test]
+    fn currencies() {
+        let mut ledger = Ledger::default();
+        let eur = Some("EUR".into());
+
+        ledger
+            .transfer(
+                &None,
+                Account::CashIn,
+                Account::Available(1),
+                Decimal::new(10, 0),
+            )
+            .unwrap();
+        // Conversion balances in every currency, but not across them
+        ledger
+            .post(&[
+                Entry {
+                    account: Account::Available(1),
+                    currency: None,
+                    amount: Decimal::new(-10, 0),
+                },
+                Entry {
+                    account: Account::Exchange,
+                    currency: None,
+                    amount: Decimal::new(10, 0),
+                },
+                Entry {
+                    account: Account::Exchange,
+                    currency: eur.clone(),
+                    amount: Decimal::new(-9, 0),
+                },
+                Entry {
+                    account: Account::Available(1),
+                    currency: eur.clone(),
+                    amount: Decimal::new(9, 0),
+                },
+            ])
+            .unwrap();
+        assert!(ledger
+            .post(&[
+                Entry {
+                    account: Account::Available(1),
+                    currency: eur.clone(),
+                    amount: Decimal::new(-9, 0),
+                },
+                Entry {
+                    account: Account::CashOut,
+                    currency: None,
+                    amount: Decimal::new(9, 0),
+                },
+            ])
+            .is_err());
+
+        assert_eq!(
+            ledger.client_balance(&None, Account::Available(1)),
+            Decimal::new(0, 0)
+        );
+        assert_eq!(
+            ledger.client_balance(&eur, Account::Available(1)),
+            Decimal::new(9, 0)
+        );
+        ledger.ensure_balanced().unwrap();
+
+        let mut buf = vec![];
+        store_csv(std::io::Cursor::new(&mut buf), ledger.balances()).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            r#"account,balance
+available:1,0.0
+cash_in,-10.0
+exchange,10.0
+available:1:EUR,9.0
+exchange:EUR,-9.0
+"#
+        );
+    }
+}
@@ -0,0 +1,27 @@
+//! Simple payments engine - reads transactions, applies them to clients accounts, and outputs
+//! final state of accounts.
+//!
+//! Binary is just a thin wrapper over this library, which exposes engine to be queried directly.
+//! Reacting to what engine does is possible by registering `engine::EngineObserver`.
+
+pub mod cli;
+pub mod client;
+pub mod config;
+pub mod decimal;
+pub mod dispute;
+pub mod engine;
+pub mod fees;
+pub mod fx;
+pub mod interest;
+pub mod ledger;
+pub mod metrics;
+pub mod output;
+pub mod rejection;
+pub mod risk;
+pub mod rules;
+pub mod schema;
+pub mod statement;
+pub mod tenant;
+pub mod timeline;
+pub mod transaction;
+pub mod transaction_type;
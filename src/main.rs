@@ -1,29 +1,187 @@
 use anyhow::{anyhow, Result};
-use log::warn;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io::IsTerminal;
+use std::rc::Rc;
+use tracing::{error, error_span, info, warn};
+use tracing_subscriber::EnvFilter;
+use transaction::tenant::{tenant_path, Tenant, Tenants};
+use transaction::transaction::{read_transactions_file, Transaction};
+use transaction::{cli, client, config, fx, metrics, output, risk, statement, timeline};
 
-mod client;
-mod decimal;
-mod engine;
-mod transaction;
-mod transaction_type;
+/// Sets up logging to stderr, filtered by `RUST_LOG` (errors only by default). Colors are used only
+/// if stderr is a terminal.
+fn init_logging(format: cli::LogFormat) {
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal())
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("error")),
+        );
+
+    match format {
+        cli::LogFormat::Text => subscriber.init(),
+        cli::LogFormat::Json => subscriber.json().init(),
+    }
+}
 
 fn main() -> Result<()> {
-    pretty_env_logger::init();
-
-    let path = std::env::args()
-        // App name
-        .skip(1)
-        .next()
-        .ok_or_else(|| anyhow!("Missing input file"))?;
-
-    let transactions =
-        transaction::read_transactions(std::fs::File::open(path)?).filter_map(|t| match t {
-            Ok(t) => Some(t),
-            Err(err) => {
-                warn!("Transaction parse error, rejecting: {}", err);
-                None
+    let args = cli::Args::from_env()?;
+    init_logging(args.log_format);
+    let _file = error_span!("file", path = %args.input.display()).entered();
+
+    let mut config = match &args.config {
+        Some(path) => config::Config::load(path)?,
+        None => config::Config::default(),
+    };
+    if let Some(path) = &args.fx_rates {
+        config.fx.rates = fx::read_rates_file(path)?;
+    }
+
+    let metrics = Rc::new(RefCell::new(metrics::Metrics::default()));
+    let parse_metrics = metrics.clone();
+    let schema = config.input_schema(args.input_schema.as_deref())?;
+    let transactions = read_transactions_file(&args.input, schema)?;
+    // Output format depends only on input columns, not on values in them
+    let tenanted = transactions.has_tenant_column();
+    let multi_currency = transactions.has_currency_column();
+    let transactions = transactions.filter_map(move |t| match t {
+        Ok(t) => Some(t),
+        Err(err) => {
+            let position = err.position.as_ref();
+            warn!(
+                line = position.map(|position| position.line),
+                byte = position.map(|position| position.byte),
+                reason = err.reason().as_str(),
+                error = %err.error,
+                "Rejecting transaction"
+            );
+            parse_metrics.borrow_mut().parse_error(err.reason());
+            None
+        }
+    });
+    match args.command {
+        cli::Command::Process => (),
+        cli::Command::Statement { cid } => {
+            let tenant = args.tenant.as_deref();
+            let transactions = transactions.filter(|record| record.tenant.as_deref() == tenant);
+            let lines = statement::statement(transactions, &config.for_tenant(tenant), cid);
+            return output::store_csv(std::io::stdout(), lines);
+        }
+        cli::Command::Balance { cid, point } => {
+            let tenant = args.tenant.as_deref();
+            let transactions = transactions.filter(|record| record.tenant.as_deref() == tenant);
+            let client = timeline::timeline(transactions, &config.for_tenant(tenant), cid)
+                .balance(cid, point)?;
+            return client::store_clients(std::io::stdout(), Some(client));
+        }
+    }
+
+    let mut scorers = BTreeMap::<Tenant, risk::RiskScorer>::new();
+    let mut tenants = Tenants::new(&config).with_observer(metrics.clone());
+    tenants.process_all(transactions, |tenant, engine, record, result| {
+        scorers
+            .entry(tenant.clone())
+            .or_default()
+            .record(engine, &record.transaction, result)
+    });
+
+    for (tenant, engine) in tenants.engines_mut() {
+        let _tenant = error_span!("tenant", tenant = tenant.as_deref()).entered();
+        let path = |path| tenant_path(path, tenant.as_deref());
+
+        if let Some(report) = &args.risk_report {
+            let scores = scorers
+                .get(tenant)
+                .map_or_else(Vec::new, risk::RiskScorer::report);
+            output::store_csv(std::fs::File::create(path(report))?, scores)?;
+        }
+
+        if let Some(period) = args.accrue_interest {
+            let config = config.for_tenant(tenant.as_deref());
+            let interest = config
+                .interest
+                .as_ref()
+                .ok_or_else(|| anyhow!("Interest accrual requested, but no interest configured"))?;
+            for transaction in engine.accrue_interest(interest, period)? {
+                if let Transaction::Interest { cid, tx, amount } = transaction {
+                    info!(tx, client = cid, amount = %amount, "Interest accrued");
+                }
             }
-        });
-    let output = engine::process(transactions)?;
-    client::store_clients(std::io::stdout(), output)
+        }
+    }
+
+    if let Some(path) = &args.metrics {
+        metrics::store_metrics(std::fs::File::create(path)?, &metrics.borrow())?;
+    }
+
+    for (tenant, engine) in tenants.engines_mut() {
+        let _tenant = error_span!("tenant", tenant = tenant.as_deref()).entered();
+        let path = |path| tenant_path(path, tenant.as_deref());
+
+        for (rule, count) in engine.rule_violations() {
+            warn!(rule = %rule, count, "Rule rejected transactions");
+        }
+
+        let stranded = engine.stranded_disputes();
+        for dispute in &stranded {
+            warn!(
+                tx = dispute.tx,
+                client = dispute.cid,
+                amount = %dispute.amount,
+                "Dispute stranded on locked client"
+            );
+        }
+        if let Some(report) = &args.stranded_report {
+            output::store_csv(std::fs::File::create(path(report))?, stranded)?;
+        }
+
+        for violation in engine.violations() {
+            error!(
+                seq = violation.seq,
+                invariant = ?violation.invariant,
+                tx = violation.tx,
+                client = violation.cid,
+                details = %violation.details,
+                "Invariant violated"
+            );
+        }
+        if let Some(report) = &args.invariants_report {
+            output::store_csv(std::fs::File::create(path(report))?, engine.violations())?;
+        }
+
+        engine.check_books()?;
+        if let Some(report) = &args.trial_balance {
+            output::store_csv(std::fs::File::create(path(report))?, engine.trial_balance())?;
+        }
+    }
+
+    if multi_currency {
+        // Base currency is labeled with its name, if configured
+        let base = config.fx.base.as_deref().unwrap_or_default();
+        client::store_labeled_clients(
+            std::io::stdout(),
+            tenanted,
+            Some(base),
+            tenants.into_engines().flat_map(|(tenant, engine)| {
+                engine
+                    .into_currency_clients()
+                    .map(move |(currency, client)| (tenant.clone(), currency, client))
+            }),
+        )
+    } else if tenanted {
+        client::store_tenant_clients(
+            std::io::stdout(),
+            tenants.into_engines().flat_map(|(tenant, engine)| {
+                engine
+                    .into_clients()
+                    .map(move |client| (tenant.clone(), client))
+            }),
+        )
+    } else {
+        let clients = tenants
+            .into_engines()
+            .flat_map(|(_, engine)| engine.into_clients());
+        client::store_clients(std::io::stdout(), clients)
+    }
 }
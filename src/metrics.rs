@@ -0,0 +1,344 @@
+use crate::client::Client;
+use crate::decimal::{Decimal, WideDecimal};
+use crate::dispute::DisputeState;
+use crate::engine::{EngineObserver, Event};
+use crate::rejection::Reason;
+use crate::transaction_type::TransactionType;
+use anyhow::{Error, Result};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+/// Prefix of all metric names, so they don't collide with metrics of other exporters
+const NAMESPACE: &str = "transaction";
+
+/// Upper bounds of transaction amount histogram buckets
+const AMOUNT_BUCKETS: [i64; 6] = [1, 10, 100, 1_000, 10_000, 100_000];
+
+/// Processing metrics, collected by observing engine, and rendered in Prometheus text format
+#[derive(Debug)]
+pub struct Metrics {
+    started: Instant,
+    /// Processed transactions by type and outcome (accepted, rejected or duplicate)
+    transactions: BTreeMap<(TransactionType, &'static str), u64>,
+    rejections: BTreeMap<Reason, u64>,
+    parse_errors: u64,
+    clients_created: u64,
+    clients_locked: u64,
+    open_disputes: i64,
+    /// Totals of all clients are wide, so they never overflow
+    held: WideDecimal,
+    /// Count of accepted amounts in every bucket (not cumulative), the last one is `+Inf`
+    amount_buckets: [u64; AMOUNT_BUCKETS.len() + 1],
+    amount_sum: WideDecimal,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            transactions: BTreeMap::new(),
+            rejections: BTreeMap::new(),
+            parse_errors: 0,
+            clients_created: 0,
+            clients_locked: 0,
+            open_disputes: 0,
+            held: WideDecimal::default(),
+            amount_buckets: Default::default(),
+            amount_sum: WideDecimal::default(),
+        }
+    }
+}
+
+impl Metrics {
+    /// Counts input record which didn't parse, so never reached the engine
+    pub fn parse_error(&mut self, reason: Reason) {
+        self.parse_errors += 1;
+        *self.rejections.entry(reason).or_default() += 1;
+    }
+
+    fn processed(&mut self, event: &Event, outcome: &'static str) {
+        *self
+            .transactions
+            .entry((event.transaction.ttype(), outcome))
+            .or_default() += 1;
+
+        let held = |client: Option<&Client>| {
+            WideDecimal::from(client.map_or(Decimal::new(0, 0), |client| client.held))
+        };
+        self.held += held(event.after) - held(event.before);
+    }
+
+    /// Renders metrics in Prometheus text format, with processing time measured up to now
+    pub fn render(&self) -> String {
+        self.render_with(self.started.elapsed())
+    }
+
+    fn render_with(&self, elapsed: Duration) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, String)]| {
+            // Writing to string never fails
+            let _ = writeln!(out, "# HELP {}_{} {}", NAMESPACE, name, help);
+            let _ = writeln!(out, "# TYPE {}_{} {}", NAMESPACE, name, kind);
+            for (labels, value) in samples {
+                let _ = writeln!(out, "{}_{}{} {}", NAMESPACE, name, labels, value);
+            }
+        };
+        let single = |value: String| vec![(String::new(), value)];
+
+        let transactions: Vec<_> = self
+            .transactions
+            .iter()
+            .map(|((ttype, outcome), count)| {
+                (
+                    format!("{{type=\"{}\",outcome=\"{}\"}}", ttype.as_str(), outcome),
+                    count.to_string(),
+                )
+            })
+            .collect();
+        metric(
+            "processed_total",
+            "counter",
+            "Processed transactions by type and outcome",
+            &transactions,
+        );
+
+        let rejections: Vec<_> = self
+            .rejections
+            .iter()
+            .map(|(reason, count)| (format!("{{reason=\"{}\"}}", reason), count.to_string()))
+            .collect();
+        metric(
+            "rejected_total",
+            "counter",
+            "Rejected transactions by reason",
+            &rejections,
+        );
+        metric(
+            "parse_errors_total",
+            "counter",
+            "Input records which didn't parse",
+            &single(self.parse_errors.to_string()),
+        );
+        metric(
+            "clients_created_total",
+            "counter",
+            "Clients which appeared in input",
+            &single(self.clients_created.to_string()),
+        );
+        metric(
+            "clients_locked_total",
+            "counter",
+            "Clients locked by chargebacks",
+            &single(self.clients_locked.to_string()),
+        );
+        metric(
+            "disputes_open",
+            "gauge",
+            "Currently open disputes",
+            &single(self.open_disputes.to_string()),
+        );
+        metric(
+            "held_funds",
+            "gauge",
+            "Funds held by open disputes of all clients",
+            &single(self.held.to_string()),
+        );
+
+        let mut cumulative = 0;
+        let mut buckets: Vec<_> = AMOUNT_BUCKETS
+            .iter()
+            .map(|bound| bound.to_string())
+            .chain(Some("+Inf".to_owned()))
+            .zip(self.amount_buckets.iter())
+            .map(|(bound, count)| {
+                cumulative += count;
+                (
+                    format!("_bucket{{le=\"{}\"}}", bound),
+                    cumulative.to_string(),
+                )
+            })
+            .collect();
+        buckets.push(("_sum".to_owned(), self.amount_sum.to_string()));
+        buckets.push(("_count".to_owned(), cumulative.to_string()));
+        metric(
+            "amount",
+            "histogram",
+            "Amounts of accepted deposits and withdrawals",
+            &buckets,
+        );
+
+        let processed: u64 = self.transactions.values().sum();
+        let seconds = elapsed.as_secs_f64();
+        metric(
+            "processing_seconds",
+            "gauge",
+            "Time spent on processing",
+            &single(seconds.to_string()),
+        );
+        let throughput = if seconds > 0.0 {
+            processed as f64 / seconds
+        } else {
+            0.0
+        };
+        metric(
+            "processing_throughput",
+            "gauge",
+            "Processed transactions per second",
+            &single(throughput.to_string()),
+        );
+
+        out
+    }
+}
+
+impl EngineObserver for Metrics {
+    fn on_accepted(&mut self, event: &Event) {
+        self.processed(event, "accepted");
+
+        if let (TransactionType::Deposit | TransactionType::Withdrawal, Some(amount)) =
+            (event.transaction.ttype(), event.transaction.amount())
+        {
+            let bucket = AMOUNT_BUCKETS
+                .iter()
+                .position(|bound| amount <= Decimal::new(*bound, 0))
+                .unwrap_or(AMOUNT_BUCKETS.len());
+            self.amount_buckets[bucket] += 1;
+            self.amount_sum += amount.into();
+        }
+    }
+
+    fn on_rejected(&mut self, event: &Event, reason: &Error) {
+        self.processed(event, "rejected");
+        *self.rejections.entry(Reason::of(reason)).or_default() += 1;
+    }
+
+    fn on_duplicate(&mut self, event: &Event) {
+        self.processed(event, "duplicate");
+    }
+
+    fn on_client_created(&mut self, _client: &Client) {
+        self.clients_created += 1;
+    }
+
+    fn on_locked(&mut self, _event: &Event) {
+        self.clients_locked += 1;
+    }
+
+    fn on_dispute_opened(&mut self, _event: &Event) {
+        self.open_disputes += 1;
+    }
+
+    fn on_dispute_closed(&mut self, _event: &Event, _state: DisputeState) {
+        self.open_disputes -= 1;
+    }
+}
+
+/// Stores metrics in Prometheus text format
+pub fn store_metrics(mut writer: impl std::io::Write, metrics: &Metrics) -> Result<()> {
+    writer.write_all(metrics.render().as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::Metrics;
+    use crate::decimal::Decimal;
+    use crate::engine::Engine;
+    use crate::rejection::Reason;
+    use crate::transaction::Transaction;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    #[test]
+    fn render() {
+        let metrics = Rc::new(RefCell::new(Metrics::default()));
+        let mut engine = Engine::default().with_observer(metrics.clone());
+
+        for transaction in [
+            Transaction::Deposit {
+                cid: 1,
+                tx: 1,
+                amount: Decimal::new(5, 0),
+            },
+            Transaction::Deposit {
+                cid: 1,
+                tx: 2,
+                amount: Decimal::new(500, 0),
+            },
+            Transaction::Dispute {
+                cid: 1,
+                tx: 2,
+                amount: None,
+            },
+            Transaction::Withdrawal {
+                cid: 1,
+                tx: 3,
+                amount: Decimal::new(10, 0),
+            },
+            Transaction::Deposit {
+                cid: 2,
+                tx: 2,
+                amount: Decimal::new(1, 0),
+            },
+            Transaction::Deposit {
+                cid: 1,
+                tx: 1,
+                amount: Decimal::new(5, 0),
+            },
+        ] {
+            engine.process_transaction(transaction).ok();
+        }
+        metrics.borrow_mut().parse_error(Reason::Parse);
+
+        assert_eq!(
+            metrics.borrow().render_with(Duration::from_secs(2)),
+            r#"# HELP transaction_processed_total Processed transactions by type and outcome
+# TYPE transaction_processed_total counter
+transaction_processed_total{type="deposit",outcome="accepted"} 2
+transaction_processed_total{type="deposit",outcome="duplicate"} 1
+transaction_processed_total{type="deposit",outcome="rejected"} 1
+transaction_processed_total{type="withdrawal",outcome="rejected"} 1
+transaction_processed_total{type="dispute",outcome="accepted"} 1
+# HELP transaction_rejected_total Rejected transactions by reason
+# TYPE transaction_rejected_total counter
+transaction_rejected_total{reason="duplicate_tx"} 1
+transaction_rejected_total{reason="insufficient_funds"} 1
+transaction_rejected_total{reason="parse"} 1
+# HELP transaction_parse_errors_total Input records which didn't parse
+# TYPE transaction_parse_errors_total counter
+transaction_parse_errors_total 1
+# HELP transaction_clients_created_total Clients which appeared in input
+# TYPE transaction_clients_created_total counter
+transaction_clients_created_total 1
+# HELP transaction_clients_locked_total Clients locked by chargebacks
+# TYPE transaction_clients_locked_total counter
+transaction_clients_locked_total 0
+# HELP transaction_disputes_open Currently open disputes
+# TYPE transaction_disputes_open gauge
+transaction_disputes_open 1
+# HELP transaction_held_funds Funds held by open disputes of all clients
+# TYPE transaction_held_funds gauge
+transaction_held_funds 500.0
+# HELP transaction_amount Amounts of accepted deposits and withdrawals
+# TYPE transaction_amount histogram
+transaction_amount_bucket{le="1"} 0
+transaction_amount_bucket{le="10"} 1
+transaction_amount_bucket{le="100"} 1
+transaction_amount_bucket{le="1000"} 2
+transaction_amount_bucket{le="10000"} 2
+transaction_amount_bucket{le="100000"} 2
+transaction_amount_bucket{le="+Inf"} 2
+transaction_amount_sum 505.0
+transaction_amount_count 2
+# HELP transaction_processing_seconds Time spent on processing
+# TYPE transaction_processing_seconds gauge
+transaction_processing_seconds 2
+# HELP transaction_processing_throughput Processed transactions per second
+# TYPE transaction_processing_throughput gauge
+transaction_processing_throughput 3
+"#
+        );
+    }
+}
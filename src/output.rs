@@ -0,0 +1,17 @@
+use anyhow::Result;
+use serde::Serialize;
+
+/// Stores records as csv, with header taken from fields of the first one. Every report is stored
+/// with it, so they all follow the same format.
+pub fn store_csv<T: Serialize>(
+    writer: impl std::io::Write,
+    records: impl IntoIterator<Item = T>,
+) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+
+    for record in records {
+        writer.serialize(record)?
+    }
+
+    Ok(())
+}
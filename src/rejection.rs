@@ -0,0 +1,116 @@
+use serde::Serialize;
+
+/// Machine readable reason of transaction rejection, so rejections can be counted and indexed
+/// without parsing messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    /// Client of transaction doesn't match client of transaction it refers to
+    ClientMismatch,
+    ClientLocked,
+    /// Transaction reuses tx of previous transaction
+    DuplicateTx,
+    /// Transaction refers to tx which was never performed
+    UnknownTx,
+    /// Only deposits can be disputed
+    NotDeposit,
+    /// Dispute is outside of the dispute window
+    DisputeWindow,
+    InvalidDisputeAmount,
+    /// Transaction is not in dispute state allowing the operation
+    InvalidDisputeState,
+    InsufficientFunds,
+    InsufficientHeld,
+    FeeNotCovered,
+    RuleViolated,
+    /// Deposit or withdrawal amount is not positive
+    InvalidAmount,
+    /// Amount given for transaction which doesn't use it, when input schema rejects it
+    UnexpectedAmount,
+    /// There is no rate converting between currencies at transaction time
+    UnknownRate,
+    /// Client balance would not fit in decimal
+    Overflow,
+    /// Transaction doesn't parse
+    Parse,
+    /// Anything not classified above
+    Other,
+}
+
+impl Reason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::ClientMismatch => "client_mismatch",
+            Self::ClientLocked => "client_locked",
+            Self::DuplicateTx => "duplicate_tx",
+            Self::UnknownTx => "unknown_tx",
+            Self::NotDeposit => "not_deposit",
+            Self::DisputeWindow => "dispute_window",
+            Self::InvalidDisputeAmount => "invalid_dispute_amount",
+            Self::InvalidDisputeState => "invalid_dispute_state",
+            Self::InsufficientFunds => "insufficient_funds",
+            Self::InsufficientHeld => "insufficient_held",
+            Self::FeeNotCovered => "fee_not_covered",
+            Self::RuleViolated => "rule_violated",
+            Self::InvalidAmount => "invalid_amount",
+            Self::UnexpectedAmount => "unexpected_amount",
+            Self::UnknownRate => "unknown_rate",
+            Self::Overflow => "overflow",
+            Self::Parse => "parse",
+            Self::Other => "other",
+        }
+    }
+
+    /// Returns reason of error, `Other` if error is not a rejection
+    pub fn of(err: &anyhow::Error) -> Self {
+        err.downcast_ref::<Rejection>()
+            .map_or(Self::Other, |rejection| rejection.reason)
+    }
+}
+
+impl std::fmt::Display for Reason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Transaction rejection error - human readable message, with reason code attached
+#[derive(Debug)]
+pub struct Rejection {
+    pub reason: Reason,
+    pub message: String,
+}
+
+impl std::fmt::Display for Rejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Rejection {}
+
+/// Creates rejection error like `anyhow!`, with reason code as the first argument
+macro_rules! reject {
+    ($reason:expr, $($arg:tt)+) => {
+        anyhow::Error::new($crate::rejection::Rejection {
+            reason: $reason,
+            message: format!($($arg)+),
+        })
+    };
+}
+
+pub(crate) use reject;
+
+#[cfg(test)]
+mod test {
+    use super::Reason;
+    use anyhow::anyhow;
+
+    #[test]
+    fn reason() {
+        let err = reject!(Reason::ClientLocked, "Client is locked, client id: {}", 1);
+        assert_eq!(err.to_string(), "Client is locked, client id: 1");
+        assert_eq!(Reason::of(&err), Reason::ClientLocked);
+        assert_eq!(Reason::of(&anyhow!("Something")), Reason::Other);
+    }
+}
@@ -0,0 +1,271 @@
+use crate::decimal::Decimal;
+use crate::engine::{Engine, Outcome};
+use crate::transaction::{ClientId, Transaction, TxId};
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// Score added for every withdrawal taking whole deposit right after it was made
+const QUICK_WITHDRAWAL_SCORE: u64 = 3;
+/// Score added for every dispute on transaction which was already disputed
+const REDISPUTE_SCORE: u64 = 2;
+/// Score added for every disputed deposit besides the first one
+const DISPUTED_DEPOSIT_SCORE: u64 = 1;
+/// Score added for every transaction reusing tx of other client
+const TX_COLLISION_SCORE: u64 = 5;
+
+/// Soft fraud signals of single client, with overall risk score
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RiskReport {
+    #[serde(rename = "client")]
+    pub cid: ClientId,
+    pub score: u64,
+    /// Withdrawals of at least the deposit amount, directly following the deposit
+    pub quick_withdrawals: u64,
+    /// Disputes (accepted or not) on transactions which were disputed before
+    pub redisputes: u64,
+    /// Distinct deposits which were disputed
+    pub disputed_deposits: u64,
+    /// Transactions rejected, because their tx belongs to other client
+    pub tx_collisions: u64,
+}
+
+/// Client activity signals are based on
+#[derive(Debug, Default)]
+struct Signals {
+    /// Amount of deposit, if it was the last accepted client transaction
+    last_deposit: Option<Decimal>,
+    quick_withdrawals: u64,
+    redisputes: u64,
+    disputed_deposits: HashSet<TxId>,
+    tx_collisions: u64,
+}
+
+/// Observes processed transactions (both accepted and rejected), collecting fraud signals.
+///
+/// It only watches the engine, so balances are never affected - signals are for humans to triage,
+/// rules rejecting transactions are in `rules` module.
+#[derive(Debug, Default)]
+pub struct RiskScorer {
+    clients: HashMap<ClientId, Signals>,
+    /// Transactions which dispute was attempted on, by client attempting it - dispute of other
+    /// client is not a redispute
+    disputed: HashSet<(ClientId, TxId)>,
+}
+
+impl RiskScorer {
+    /// Records transaction outcome, to be called after every processed transaction
+    pub fn record(&mut self, engine: &Engine, transaction: &Transaction, result: &Result<Outcome>) {
+        // Retries of upstream are not suspicious
+        if let Ok(Outcome::Duplicate) = result {
+            return;
+        }
+
+        let cid = transaction.cid();
+        let signals = self.clients.entry(cid).or_default();
+        let accepted = result.is_ok();
+
+        match transaction {
+            Transaction::Deposit { tx, .. } | Transaction::Withdrawal { tx, .. } if !accepted => {
+                let owner = engine.transaction_client(*tx);
+                if owner.is_some_and(|owner| owner != cid) {
+                    signals.tx_collisions += 1;
+                }
+            }
+            Transaction::Deposit { amount, .. } => {
+                signals.last_deposit = Some(*amount);
+                return;
+            }
+            Transaction::Withdrawal { amount, .. }
+                if signals
+                    .last_deposit
+                    .is_some_and(|deposit| *amount >= deposit) =>
+            {
+                signals.quick_withdrawals += 1;
+            }
+            Transaction::Dispute { tx, .. } => {
+                if !self.disputed.insert((cid, *tx)) {
+                    signals.redisputes += 1;
+                }
+                if accepted {
+                    signals.disputed_deposits.insert(*tx);
+                }
+            }
+            _ => (),
+        }
+
+        if accepted {
+            signals.last_deposit = None;
+        }
+    }
+
+    /// Returns report of clients with any signal, the riskiest first
+    pub fn report(&self) -> Vec<RiskReport> {
+        let mut report: Vec<_> = self
+            .clients
+            .iter()
+            .map(|(cid, signals)| {
+                let disputed_deposits = signals.disputed_deposits.len() as u64;
+                let score = signals.quick_withdrawals * QUICK_WITHDRAWAL_SCORE
+                    + signals.redisputes * REDISPUTE_SCORE
+                    + disputed_deposits.saturating_sub(1) * DISPUTED_DEPOSIT_SCORE
+                    + signals.tx_collisions * TX_COLLISION_SCORE;
+
+                RiskReport {
+                    cid: *cid,
+                    score,
+                    quick_withdrawals: signals.quick_withdrawals,
+                    redisputes: signals.redisputes,
+                    disputed_deposits,
+                    tx_collisions: signals.tx_collisions,
+                }
+            })
+            .filter(|report| {
+                report.quick_withdrawals
+                    + report.redisputes
+                    + report.disputed_deposits
+                    + report.tx_collisions
+                    > 0
+            })
+            .collect();
+
+        report.sort_by_key(|report| (std::cmp::Reverse(report.score), report.cid));
+        report
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RiskReport, RiskScorer};
+    use crate::config::Config;
+    use crate::decimal::Decimal;
+    use crate::engine;
+    use crate::transaction::Transaction;
+
+    #[test]
+    fn signals() {
+        let transactions = vec![
+            Transaction::Deposit {
+                cid: 1,
+                tx: 1,
+                amount: Decimal::new(100, 0),
+            },
+            Transaction::Withdrawal {
+                cid: 1,
+                tx: 2,
+                amount: Decimal::new(100, 0),
+            },
+            Transaction::Deposit {
+                cid: 2,
+                tx: 3,
+                amount: Decimal::new(50, 0),
+            },
+            Transaction::Deposit {
+                cid: 2,
+                tx: 4,
+                amount: Decimal::new(50, 0),
+            },
+            Transaction::Dispute {
+                cid: 2,
+                tx: 3,
+                amount: None,
+            },
+            Transaction::Resolve { cid: 2, tx: 3 },
+            // Rejected by default policy, but still suspicious
+            Transaction::Dispute {
+                cid: 2,
+                tx: 3,
+                amount: None,
+            },
+            Transaction::Dispute {
+                cid: 2,
+                tx: 4,
+                amount: None,
+            },
+            // Collides with deposit of client 2
+            Transaction::Deposit {
+                cid: 3,
+                tx: 4,
+                amount: Decimal::new(1, 0),
+            },
+            // Duplicate of own transaction is not a collision
+            Transaction::Deposit {
+                cid: 2,
+                tx: 3,
+                amount: Decimal::new(1, 0),
+            },
+        ];
+
+        let mut scorer = RiskScorer::default();
+        engine::process_with(
+            transactions.into_iter().map(Into::into),
+            &Config::default(),
+            |engine, record, result| scorer.record(engine, &record.transaction, result),
+        );
+
+        assert_eq!(
+            scorer.report(),
+            vec![
+                RiskReport {
+                    cid: 3,
+                    score: 5,
+                    tx_collisions: 1,
+                    ..RiskReport::default()
+                },
+                RiskReport {
+                    cid: 1,
+                    score: 3,
+                    quick_withdrawals: 1,
+                    ..RiskReport::default()
+                },
+                RiskReport {
+                    cid: 2,
+                    score: 3,
+                    redisputes: 1,
+                    disputed_deposits: 2,
+                    ..RiskReport::default()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn cross_client_disputes() {
+        let transactions = vec![
+            Transaction::Deposit {
+                cid: 1,
+                tx: 1,
+                amount: Decimal::new(100, 0),
+            },
+            // Rejected, as tx belongs to client 1
+            Transaction::Dispute {
+                cid: 2,
+                tx: 1,
+                amount: None,
+            },
+            // First dispute of the owner, not a redispute
+            Transaction::Dispute {
+                cid: 1,
+                tx: 1,
+                amount: None,
+            },
+        ];
+
+        let mut scorer = RiskScorer::default();
+        engine::process_with(
+            transactions.into_iter().map(Into::into),
+            &Config::default(),
+            |engine, record, result| scorer.record(engine, &record.transaction, result),
+        );
+
+        assert_eq!(
+            scorer.report(),
+            vec![RiskReport {
+                cid: 1,
+                score: 0,
+                disputed_deposits: 1,
+                ..RiskReport::default()
+            }]
+        );
+    }
+}
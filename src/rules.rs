@@ -0,0 +1,306 @@
+use crate::decimal::Decimal;
+use crate::rejection::{reject, Reason};
+use crate::transaction::{ClientId, Timestamp, DAY};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+/// Limits on client activity, protecting from abuse. Every limit is disabled if not set.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Rules {
+    /// Biggest amount of single withdrawal
+    pub max_withdrawal: Option<Decimal>,
+    /// Biggest sum of withdrawals within single day (UTC). Withdrawals without timestamp are not
+    /// limited by it.
+    pub max_daily_withdrawal: Option<Decimal>,
+    /// How many withdrawals there may be among client recent transactions
+    pub withdrawal_frequency: Option<WithdrawalFrequency>,
+    /// How many deposits client may perform before its first withdrawal
+    pub max_deposits_before_withdrawal: Option<u32>,
+}
+
+/// Allows at most `max_withdrawals` withdrawals among `transactions` client recent deposits and
+/// withdrawals (including the one being checked)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WithdrawalFrequency {
+    pub max_withdrawals: u32,
+    pub transactions: u32,
+}
+
+/// Single rule which can reject transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rule {
+    MaxWithdrawal,
+    MaxDailyWithdrawal,
+    WithdrawalFrequency,
+    MaxDepositsBeforeWithdrawal,
+}
+
+impl std::fmt::Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MaxWithdrawal => write!(f, "max_withdrawal"),
+            Self::MaxDailyWithdrawal => write!(f, "max_daily_withdrawal"),
+            Self::WithdrawalFrequency => write!(f, "withdrawal_frequency"),
+            Self::MaxDepositsBeforeWithdrawal => write!(f, "max_deposits_before_withdrawal"),
+        }
+    }
+}
+
+/// Client activity which rules are verified against - only accepted transactions are recorded
+#[derive(Debug, Default)]
+struct Activity {
+    /// Day of the last timestamped withdrawal, and withdrawals sum on that day
+    daily: Option<(u64, Decimal)>,
+    /// Whether recent deposits and withdrawals were withdrawals, the newest at the back
+    recent: VecDeque<bool>,
+    /// Deposits performed so far, `None` after first withdrawal
+    deposits: Option<u32>,
+}
+
+/// Verifies rules before funds are moved, counting violations of every rule
+#[derive(Debug, Default)]
+pub struct RuleChecker {
+    rules: Rules,
+    clients: HashMap<ClientId, Activity>,
+    violations: HashMap<Rule, u64>,
+}
+
+impl RuleChecker {
+    pub fn new(rules: Rules) -> Self {
+        Self {
+            rules,
+            ..Self::default()
+        }
+    }
+
+    fn violation(&mut self, rule: Rule, details: String) -> Result<()> {
+        *self.violations.entry(rule).or_default() += 1;
+        Err(reject!(
+            Reason::RuleViolated,
+            "Rule {} violated, {}",
+            rule,
+            details
+        ))
+    }
+
+    /// Checks if withdrawal is allowed, returning error otherwise
+    pub fn check_withdrawal(
+        &mut self,
+        cid: ClientId,
+        amount: Decimal,
+        timestamp: Option<Timestamp>,
+    ) -> Result<()> {
+        match self.broken_withdrawal_rule(cid, amount, timestamp) {
+            Some((rule, details)) => self.violation(rule, details),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns first rule broken by withdrawal, with violation details
+    fn broken_withdrawal_rule(
+        &self,
+        cid: ClientId,
+        amount: Decimal,
+        timestamp: Option<Timestamp>,
+    ) -> Option<(Rule, String)> {
+        let rules = &self.rules;
+        let activity = self.clients.get(&cid);
+
+        if let Some(max) = rules.max_withdrawal.filter(|max| amount > *max) {
+            return Some((
+                Rule::MaxWithdrawal,
+                format!("cid: {}, amount: {}, max: {}", cid, amount, max),
+            ));
+        }
+
+        if let (Some(max), Some(timestamp)) = (rules.max_daily_withdrawal, timestamp) {
+            let today = match activity.and_then(|activity| activity.daily) {
+                Some((day, sum)) if day == timestamp / DAY => sum,
+                _ => Decimal::new(0, 0),
+            };
+            if today + amount > max {
+                return Some((
+                    Rule::MaxDailyWithdrawal,
+                    format!(
+                        "cid: {}, amount: {}, withdrawn today: {}, max: {}",
+                        cid, amount, today, max
+                    ),
+                ));
+            }
+        }
+
+        if let Some(frequency) = rules.withdrawal_frequency {
+            // Recent transactions without the one being checked
+            let window = frequency.transactions.saturating_sub(1) as usize;
+            let withdrawals = activity.map_or(0, |activity| {
+                activity
+                    .recent
+                    .iter()
+                    .rev()
+                    .take(window)
+                    .filter(|withdrawal| **withdrawal)
+                    .count()
+            });
+            if withdrawals + 1 > frequency.max_withdrawals as usize {
+                return Some((
+                    Rule::WithdrawalFrequency,
+                    format!(
+                        "cid: {}, more than {} withdrawals in {} transactions",
+                        cid, frequency.max_withdrawals, frequency.transactions
+                    ),
+                ));
+            }
+        }
+
+        None
+    }
+
+    /// Checks if deposit is allowed, returning error otherwise
+    pub fn check_deposit(&mut self, cid: ClientId) -> Result<()> {
+        let deposits = self
+            .clients
+            .get(&cid)
+            .map_or(Some(0), |activity| activity.deposits);
+
+        match (self.rules.max_deposits_before_withdrawal, deposits) {
+            (Some(max), Some(deposits)) if deposits >= max => self.violation(
+                Rule::MaxDepositsBeforeWithdrawal,
+                format!("cid: {}, {} deposits without withdrawal", cid, deposits),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    fn activity(&mut self, cid: ClientId) -> &mut Activity {
+        let activity = self.clients.entry(cid).or_insert_with(|| Activity {
+            deposits: Some(0),
+            ..Activity::default()
+        });
+
+        // Only as many transactions as frequency rule looks at are needed
+        let window = self
+            .rules
+            .withdrawal_frequency
+            .map_or(0, |frequency| frequency.transactions as usize);
+        if activity.recent.len() >= window {
+            activity.recent.pop_front();
+        }
+
+        activity
+    }
+
+    /// Records accepted withdrawal
+    pub fn withdrawn(&mut self, cid: ClientId, amount: Decimal, timestamp: Option<Timestamp>) {
+        let activity = self.activity(cid);
+        activity.recent.push_back(true);
+        activity.deposits = None;
+
+        if let Some(day) = timestamp.map(|timestamp| timestamp / DAY) {
+            activity.daily = match activity.daily {
+                Some((last, sum)) if last == day => Some((day, sum + amount)),
+                _ => Some((day, amount)),
+            };
+        }
+    }
+
+    /// Records accepted deposit
+    pub fn deposited(&mut self, cid: ClientId) {
+        let activity = self.activity(cid);
+        activity.recent.push_back(false);
+        if let Some(deposits) = &mut activity.deposits {
+            *deposits += 1;
+        }
+    }
+
+    /// Returns number of transactions rejected by every rule, ordered by rule
+    pub fn violations(&self) -> Vec<(Rule, u64)> {
+        let mut violations: Vec<_> = self
+            .violations
+            .iter()
+            .map(|(rule, count)| (*rule, *count))
+            .collect();
+        violations.sort_unstable();
+        violations
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Rule, RuleChecker, Rules, WithdrawalFrequency};
+    use crate::decimal::Decimal;
+    use crate::transaction::DAY;
+
+    #[test]
+    fn withdrawal_rules() {
+        let mut checker = RuleChecker::new(Rules {
+            max_withdrawal: Some(Decimal::new(100, 0)),
+            max_daily_withdrawal: Some(Decimal::new(150, 0)),
+            withdrawal_frequency: Some(WithdrawalFrequency {
+                max_withdrawals: 2,
+                transactions: 3,
+            }),
+            ..Rules::default()
+        });
+        let mut withdraw = |amount, timestamp| {
+            let amount = Decimal::new(amount, 0);
+            let result = checker.check_withdrawal(1, amount, timestamp);
+            if result.is_ok() {
+                checker.withdrawn(1, amount, timestamp);
+            }
+            result.is_ok()
+        };
+
+        assert!(!withdraw(101, None));
+        assert!(withdraw(100, Some(DAY)));
+        assert!(!withdraw(51, Some(DAY + 10)));
+        // Doesn't count without timestamp
+        assert!(withdraw(51, None));
+        // Third withdrawal in a row
+        assert!(!withdraw(10, Some(2 * DAY)));
+
+        checker.deposited(1);
+        checker.deposited(1);
+        // Next day
+        assert!(checker
+            .check_withdrawal(1, Decimal::new(100, 0), Some(2 * DAY))
+            .is_ok());
+
+        assert_eq!(
+            checker.violations(),
+            vec![
+                (Rule::MaxWithdrawal, 1),
+                (Rule::MaxDailyWithdrawal, 1),
+                (Rule::WithdrawalFrequency, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn deposits_before_withdrawal() {
+        let mut checker = RuleChecker::new(Rules {
+            max_deposits_before_withdrawal: Some(2),
+            ..Rules::default()
+        });
+
+        for _ in 0..2 {
+            checker.check_deposit(1).unwrap();
+            checker.deposited(1);
+        }
+        assert!(checker.check_deposit(1).is_err());
+        assert!(checker.check_deposit(2).is_ok());
+
+        checker.withdrawn(1, Decimal::new(1, 0), None);
+        checker.check_deposit(1).unwrap();
+        checker.deposited(1);
+        checker.check_deposit(1).unwrap();
+
+        assert_eq!(
+            checker.violations(),
+            vec![(Rule::MaxDepositsBeforeWithdrawal, 1)]
+        );
+    }
+}
@@ -0,0 +1,263 @@
+use crate::transaction_type::TransactionType;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Deserializer};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// Columns transactions are read from
+const COLUMNS: [&str; 8] = [
+    "type",
+    "client",
+    "tx",
+    "amount",
+    "timestamp",
+    "tenant",
+    "currency",
+    "to_currency",
+];
+
+/// Format of the input csv.
+///
+/// Defaults match the original format - comma delimited file, with header naming the columns in any
+/// order. Columns which are not known are skipped, unless `ignore_unknown_columns` is turned off.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputSchema {
+    /// Fields delimiter, has to be ASCII
+    pub delimiter: char,
+    /// Quoting character, has to be ASCII
+    pub quote: char,
+    /// Whether the first row names columns. If not, columns are taken from `columns`.
+    pub has_headers: bool,
+    /// Column names in order they are in the file, used only when file has no header
+    pub columns: Vec<String>,
+    /// Other names of columns, eg. `client_id = "client"`
+    pub aliases: HashMap<String, String>,
+    /// Skips columns which are not known instead of rejecting the whole file
+    pub ignore_unknown_columns: bool,
+    /// Other names of transaction types, eg. `withdraw = "withdrawal"`. Types are matched ignoring
+    /// case, so aliases are kept lowercase.
+    #[serde(deserialize_with = "deserialize_type_aliases")]
+    pub type_aliases: HashMap<String, TransactionType>,
+    /// What to do with amount given for resolve, chargeback or chargeback reversal
+    pub unexpected_amount: Strictness,
+}
+
+/// How row which carries something never used is treated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strictness {
+    /// Accepts row, silently dropping what is not used
+    #[default]
+    Ignore,
+    /// Accepts row, logging warning
+    Warn,
+    /// Rejects row as invalid
+    Reject,
+}
+
+impl Default for InputSchema {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            quote: '"',
+            has_headers: true,
+            columns: ["type", "client", "tx", "amount"]
+                .iter()
+                .map(|column| column.to_string())
+                .collect(),
+            aliases: HashMap::new(),
+            ignore_unknown_columns: true,
+            type_aliases: HashMap::new(),
+            unexpected_amount: Strictness::default(),
+        }
+    }
+}
+
+/// Lowercases aliases, rejecting ones which would shadow type names or each other
+fn deserialize_type_aliases<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<String, TransactionType>, D::Error> {
+    use serde::de::Error;
+
+    let mut aliases = HashMap::new();
+    for (alias, ttype) in HashMap::<String, TransactionType>::deserialize(deserializer)? {
+        let alias = alias.to_ascii_lowercase();
+        if alias.parse::<TransactionType>().is_ok() {
+            return Err(D::Error::custom(format!(
+                "Type alias {} is a transaction type name",
+                alias
+            )));
+        }
+        if aliases.insert(alias.clone(), ttype).is_some() {
+            return Err(D::Error::custom(format!(
+                "Type alias {} given more than once",
+                alias
+            )));
+        }
+    }
+
+    Ok(aliases)
+}
+
+/// Converts schema character to byte, as csv reader works on bytes
+fn ascii(name: &str, c: char) -> Result<u8> {
+    if c.is_ascii() {
+        Ok(c as u8)
+    } else {
+        Err(anyhow!(
+            "Input {} has to be ASCII character, {} given",
+            name,
+            c
+        ))
+    }
+}
+
+impl InputSchema {
+    /// Creates csv reader builder for this schema
+    pub fn reader_builder(&self) -> Result<csv::ReaderBuilder> {
+        let mut builder = csv::ReaderBuilder::new();
+        builder
+            .trim(csv::Trim::All)
+            .delimiter(ascii("delimiter", self.delimiter)?)
+            .quote(ascii("quote", self.quote)?)
+            .has_headers(self.has_headers);
+        Ok(builder)
+    }
+
+    /// Maps column names (as given in header, or `columns` if there is no header) to the ones
+    /// transactions are read from
+    pub fn headers<'c>(
+        &self,
+        columns: impl IntoIterator<Item = &'c str>,
+    ) -> Result<csv::StringRecord> {
+        columns
+            .into_iter()
+            .map(|column| {
+                let column = self.aliases.get(column).map_or(column, String::as_str);
+                if self.ignore_unknown_columns || COLUMNS.contains(&column) {
+                    Ok(column)
+                } else {
+                    Err(anyhow!("Unknown input column: {}", column))
+                }
+            })
+            .collect()
+    }
+
+    /// Parses transaction type, by its name or alias
+    pub fn transaction_type(&self, name: &str) -> Result<TransactionType> {
+        if self.type_aliases.is_empty() {
+            return name.parse();
+        }
+
+        // Names are mostly lowercase already, no need to allocate for them
+        let lowercase = if name.bytes().any(|b| b.is_ascii_uppercase()) {
+            Cow::Owned(name.to_ascii_lowercase())
+        } else {
+            Cow::Borrowed(name)
+        };
+
+        match self.type_aliases.get(lowercase.as_ref()) {
+            Some(ttype) => Ok(*ttype),
+            None => name.parse(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::InputSchema;
+    use crate::transaction_type::TransactionType;
+
+    #[test]
+    fn headers() {
+        let schema = InputSchema {
+            aliases: [("client_id", "client"), ("transaction_id", "tx")]
+                .iter()
+                .map(|(alias, column)| (alias.to_string(), column.to_string()))
+                .collect(),
+            ..InputSchema::default()
+        };
+
+        assert_eq!(
+            schema
+                .headers(vec![
+                    "transaction_id",
+                    "type",
+                    "client_id",
+                    "amount",
+                    "note"
+                ])
+                .unwrap(),
+            vec!["tx", "type", "client", "amount", "note"]
+        );
+
+        let schema = InputSchema {
+            ignore_unknown_columns: false,
+            ..schema
+        };
+        assert!(schema.headers(vec!["type", "client_id", "note"]).is_err());
+
+        let schema = InputSchema {
+            delimiter: 'ł',
+            ..schema
+        };
+        assert!(schema.reader_builder().is_err());
+    }
+
+    #[test]
+    fn transaction_types() {
+        let schema = InputSchema {
+            type_aliases: [
+                ("withdraw", TransactionType::Withdrawal),
+                ("cb", TransactionType::Chargeback),
+                ("refund", TransactionType::ChargebackReversal),
+            ]
+            .iter()
+            .map(|(alias, ttype)| (alias.to_string(), *ttype))
+            .collect(),
+            ..InputSchema::default()
+        };
+
+        let parse = |name| schema.transaction_type(name).ok();
+        assert_eq!(parse("Deposit"), Some(TransactionType::Deposit));
+        assert_eq!(parse("WITHDRAW"), Some(TransactionType::Withdrawal));
+        assert_eq!(parse("cb"), Some(TransactionType::Chargeback));
+        assert_eq!(parse("refund"), Some(TransactionType::ChargebackReversal));
+        assert_eq!(parse("payout"), None);
+    }
+
+    #[test]
+    fn type_aliases_config() {
+        let schema: InputSchema = toml::from_str(
+            r#"
+[type_aliases]
+Withdraw = "withdrawal"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            schema.transaction_type("withdraw").ok(),
+            Some(TransactionType::Withdrawal)
+        );
+
+        // Type names cannot be overriden
+        assert!(toml::from_str::<InputSchema>(
+            r#"
+[type_aliases]
+Deposit = "withdrawal"
+"#
+        )
+        .is_err());
+
+        // Aliases differing only by case are ambiguous
+        assert!(toml::from_str::<InputSchema>(
+            r#"
+[type_aliases]
+cb = "chargeback"
+CB = "chargeback_reversal"
+"#
+        )
+        .is_err());
+    }
+}
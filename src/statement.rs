@@ -0,0 +1,133 @@
+use crate::config::Config;
+use crate::decimal::Decimal;
+use crate::engine::{self, Engine, Outcome};
+use crate::transaction::{ClientId, Record, Transaction, TxId};
+use crate::transaction_type::TransactionType;
+use anyhow::Result;
+use serde::Serialize;
+
+/// Outcome of transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Accepted,
+    Rejected,
+    /// Re-submission of transaction accepted before, ignored
+    Duplicate,
+}
+
+/// Single statement line - transaction affecting client, and client balances after it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StatementLine {
+    /// Input line transaction was read from, empty for transactions generated by engine
+    pub line: Option<u64>,
+    #[serde(rename = "type")]
+    pub ttype: TransactionType,
+    pub tx: TxId,
+    pub amount: Option<Decimal>,
+    /// Fee charged on top of amount
+    pub fee: Option<Decimal>,
+    pub status: Status,
+    /// Rejection reason, empty for accepted transactions
+    pub reason: String,
+    pub available: Decimal,
+    pub held: Decimal,
+    pub total: Decimal,
+}
+
+impl StatementLine {
+    fn new(record: &Record, result: &Result<Outcome>, engine: &Engine) -> Self {
+        let transaction = &record.transaction;
+        let (status, reason) = match result {
+            Ok(Outcome::Applied) => (Status::Accepted, String::new()),
+            Ok(Outcome::Duplicate) => (Status::Duplicate, String::new()),
+            Err(err) => (Status::Rejected, err.to_string()),
+        };
+
+        let fee = match transaction {
+            Transaction::Deposit { tx, .. } | Transaction::Withdrawal { tx, .. }
+                if matches!(result, Ok(Outcome::Applied)) =>
+            {
+                engine.transaction_fee(*tx)
+            }
+            _ => None,
+        };
+
+        // Client may not exist if its very first transaction was rejected early
+        let client = engine.client_state(transaction.cid());
+        let (available, held) = client.map_or((Decimal::new(0, 0), Decimal::new(0, 0)), |c| {
+            (c.available, c.held)
+        });
+
+        Self {
+            line: record.position.as_ref().map(|position| position.line),
+            ttype: transaction.ttype(),
+            tx: transaction.tx(),
+            amount: transaction.amount(),
+            fee,
+            status,
+            reason,
+            available,
+            held,
+            total: available + held,
+        }
+    }
+}
+
+/// Processes all transactions, building chronological statement of given client.
+///
+/// Whole input has to be processed, as transactions of other clients may affect outcome of
+/// client transactions (eg. by tx collisions).
+pub fn statement(
+    records: impl IntoIterator<Item = Record>,
+    config: &Config,
+    cid: ClientId,
+) -> Vec<StatementLine> {
+    let mut lines = vec![];
+
+    engine::process_with(records, config, |engine, record, result| {
+        if record.transaction.cid() == cid {
+            lines.push(StatementLine::new(record, result, engine));
+        }
+    });
+
+    lines
+}
+
+#[cfg(test)]
+mod test {
+    use super::statement;
+    use crate::config::Config;
+    use crate::output::store_csv;
+    use crate::transaction::read_transactions;
+
+    #[test]
+    fn client_statement() {
+        let data = br#"type, client, tx, amount
+deposit, 1, 1, 10.0
+deposit, 2, 2, 5.0
+withdrawal, 1, 3, 20.0
+dispute, 1, 1, 4.0
+resolve, 1, 1,
+deposit, 1, 1, 10.0"#;
+
+        let lines = statement(
+            read_transactions(&data[..]).map(Result::unwrap),
+            &Config::default(),
+            1,
+        );
+
+        let mut buf = vec![];
+        store_csv(std::io::Cursor::new(&mut buf), lines).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            r#"line,type,tx,amount,fee,status,reason,available,held,total
+2,deposit,1,10.0,0.0,accepted,,10.0,0.0,10.0
+4,withdrawal,3,20.0,,rejected,"Trying to withdraw more than available, tx: 3, cid: 1, amount: 20.0, fee: 0.0",10.0,0.0,10.0
+5,dispute,1,4.0,,accepted,,6.0,4.0,10.0
+6,resolve,1,,,accepted,,10.0,0.0,10.0
+7,deposit,1,10.0,,duplicate,,10.0,0.0,10.0
+"#
+        );
+    }
+}
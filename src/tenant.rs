@@ -0,0 +1,257 @@
+use crate::config::Config;
+use crate::engine::{Engine, EngineObserver, Outcome};
+use crate::transaction::Record;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use tracing::error_span;
+
+/// Tenant transaction belongs to, `None` for transactions without tenant
+pub type Tenant = Option<Rc<str>>;
+
+/// Creates observer for engine of every tenant
+type ObserverFactory = Box<dyn Fn() -> Box<dyn EngineObserver>>;
+
+/// Engines of all tenants, so tenants never see each other clients, transactions and settings.
+///
+/// Input without tenants goes to the single engine of `None` tenant, so it is processed exactly
+/// like by a single engine.
+pub struct Tenants {
+    config: Config,
+    engines: BTreeMap<Tenant, Engine>,
+    observers: Vec<ObserverFactory>,
+}
+
+impl Tenants {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            config: config.clone(),
+            engines: BTreeMap::new(),
+            observers: vec![],
+        }
+    }
+
+    /// Registers observer in engine of every tenant. Every engine gets its own clone, so to
+    /// collect state of all tenants together it should be shared (eg. `Rc<RefCell<_>>`).
+    pub fn with_observer(mut self, observer: impl EngineObserver + Clone + 'static) -> Self {
+        self.observers
+            .push(Box::new(move || Box::new(observer.clone())));
+        self
+    }
+
+    /// Returns engine of tenant, creating it with tenant config on its first transaction
+    fn engine(&mut self, tenant: &Tenant) -> &mut Engine {
+        let (config, observers) = (&self.config, &self.observers);
+
+        self.engines.entry(tenant.clone()).or_insert_with(|| {
+            let mut engine = Engine::from_config(&config.for_tenant(tenant.as_deref()));
+            for observer in observers {
+                engine.add_observer(observer());
+            }
+            engine
+        })
+    }
+
+    /// Processes all transactions like `Engine::process_all`, every one by engine of its tenant
+    pub fn process_all(
+        &mut self,
+        records: impl IntoIterator<Item = Record>,
+        mut processed: impl FnMut(&Tenant, &Engine, &Record, &Result<Outcome>),
+    ) {
+        let _batch = error_span!("batch").entered();
+
+        for record in records {
+            let tenant = record.tenant.clone();
+            self.engine(&tenant)
+                .process_logged(record, |engine, record, result| {
+                    processed(&tenant, engine, record, result)
+                });
+        }
+
+        // Even empty input has the default tenant, so its (empty) results are stored
+        if self.engines.is_empty() {
+            self.engine(&None);
+        }
+
+        for (tenant, engine) in &mut self.engines {
+            engine.finish(|engine, record, result| processed(tenant, engine, record, result));
+        }
+    }
+
+    /// Engines of all tenants, ordered by tenant
+    pub fn engines_mut(&mut self) -> impl Iterator<Item = (&Tenant, &mut Engine)> {
+        self.engines.iter_mut()
+    }
+
+    /// Converts it to engines of all tenants, ordered by tenant
+    pub fn into_engines(self) -> impl Iterator<Item = (Tenant, Engine)> {
+        self.engines.into_iter()
+    }
+}
+
+/// Path of tenant report - tenant name is added before extension, so `stranded.csv` of tenant
+/// `brand` is `stranded.brand.csv`. Characters which are not safe in file names are percent-encoded
+/// (as well as `%` itself), so different tenants never share a file. Report of `None` tenant is
+/// stored under the path given.
+pub fn tenant_path(path: &Path, tenant: Option<&str>) -> PathBuf {
+    let tenant = match tenant {
+        Some(tenant) => tenant,
+        None => return path.to_owned(),
+    };
+
+    let tenant: String = tenant
+        .bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b == b'-' || b == b'_' {
+                (b as char).to_string()
+            } else {
+                format!("%{:02X}", b)
+            }
+        })
+        .collect();
+    let name = match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(ext)) => format!(
+            "{}.{}.{}",
+            stem.to_string_lossy(),
+            tenant,
+            ext.to_string_lossy()
+        ),
+        (Some(stem), None) => format!("{}.{}", stem.to_string_lossy(), tenant),
+        _ => tenant,
+    };
+
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{tenant_path, Tenants};
+    use crate::config::{Config, TenantConfig};
+    use crate::decimal::Decimal;
+    use crate::rules::Rules;
+    use crate::transaction::{Record, Transaction};
+    use std::path::{Path, PathBuf};
+
+    fn deposit(tenant: Option<&str>, cid: u64, tx: u64, amount: i64) -> Record {
+        Record {
+            tenant: tenant.map(Into::into),
+            ..Transaction::Deposit {
+                cid,
+                tx,
+                amount: Decimal::new(amount, 0),
+            }
+            .into()
+        }
+    }
+
+    fn withdrawal(tenant: Option<&str>, cid: u64, tx: u64, amount: i64) -> Record {
+        Record {
+            tenant: tenant.map(Into::into),
+            ..Transaction::Withdrawal {
+                cid,
+                tx,
+                amount: Decimal::new(amount, 0),
+            }
+            .into()
+        }
+    }
+
+    #[test]
+    fn isolation() {
+        let mut config = Config::default();
+        config.tenants.insert(
+            "b".to_owned(),
+            TenantConfig {
+                rules: Some(Rules {
+                    max_withdrawal: Some(Decimal::new(5, 0)),
+                    ..Rules::default()
+                }),
+                ..TenantConfig::default()
+            },
+        );
+
+        let mut tenants = Tenants::new(&config);
+        let mut rejected = vec![];
+        tenants.process_all(
+            vec![
+                deposit(Some("a"), 1, 1, 10),
+                // Same client and tx, but other tenant
+                deposit(Some("b"), 1, 1, 20),
+                withdrawal(Some("a"), 1, 2, 8),
+                // Limited only for tenant `b`
+                withdrawal(Some("b"), 1, 2, 8),
+            ],
+            |tenant, _, record, result| {
+                if result.is_err() {
+                    rejected.push((tenant.clone(), record.transaction.tx()));
+                }
+            },
+        );
+
+        assert_eq!(rejected, vec![(Some("b".into()), 2)]);
+
+        let balances: Vec<_> = tenants
+            .into_engines()
+            .flat_map(|(tenant, engine)| {
+                engine
+                    .into_clients()
+                    .map(move |client| (tenant.clone(), client.cid, client.available))
+            })
+            .collect();
+        assert_eq!(
+            balances,
+            vec![
+                (Some("a".into()), 1, Decimal::new(2, 0)),
+                (Some("b".into()), 1, Decimal::new(20, 0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn without_tenants() {
+        let mut tenants = Tenants::new(&Config::default());
+        tenants.process_all(vec![], |_, _, _, _| ());
+
+        let engines: Vec<_> = tenants.into_engines().map(|(tenant, _)| tenant).collect();
+        assert_eq!(engines, vec![None]);
+    }
+
+    #[test]
+    fn paths() {
+        let path = Path::new("reports/stranded.csv");
+        assert_eq!(
+            tenant_path(path, None),
+            PathBuf::from("reports/stranded.csv")
+        );
+        assert_eq!(
+            tenant_path(path, Some("brand")),
+            PathBuf::from("reports/stranded.brand.csv")
+        );
+        assert_eq!(
+            tenant_path(path, Some("../x")),
+            PathBuf::from("reports/stranded.%2E%2E%2Fx.csv")
+        );
+        // Tenants differing only in unsafe characters don't collide
+        assert_eq!(
+            tenant_path(path, Some("a.b")),
+            PathBuf::from("reports/stranded.a%2Eb.csv")
+        );
+        assert_eq!(
+            tenant_path(path, Some("a/b")),
+            PathBuf::from("reports/stranded.a%2Fb.csv")
+        );
+        assert_eq!(
+            tenant_path(path, Some("a%2Eb")),
+            PathBuf::from("reports/stranded.a%252Eb.csv")
+        );
+        assert_eq!(
+            tenant_path(path, Some("żółw")),
+            PathBuf::from("reports/stranded.%C5%BC%C3%B3%C5%82w.csv")
+        );
+        assert_eq!(
+            tenant_path(Path::new("metrics"), Some("brand")),
+            PathBuf::from("metrics.brand")
+        );
+    }
+}
@@ -0,0 +1,222 @@
+use crate::client::Client;
+use crate::config::Config;
+use crate::engine::{self, Engine, Outcome};
+use crate::transaction::{ClientId, Record, Timestamp, Transaction, TxId};
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+
+/// Point in processing for which balances are queried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointInTime {
+    /// Right after transaction with given tx was performed
+    AfterTx(TxId),
+    /// After all transactions up to given timestamp
+    At(Timestamp),
+}
+
+/// Client state after one of its transactions
+#[derive(Debug)]
+struct Checkpoint {
+    /// Index of transaction in processing order
+    seq: u64,
+    /// Engine time after transaction - it is the latest timestamp seen so far, so it never goes
+    /// back even if input timestamps are not ordered
+    now: Option<Timestamp>,
+    client: Client,
+}
+
+/// History of clients balances, allowing to query them at any point of processing.
+///
+/// Checkpoint is stored for every client after each of its accepted transactions, so query is
+/// just a binary search over client checkpoints, without replaying anything.
+#[derive(Debug, Default)]
+pub struct Timeline {
+    /// Client checkpoints are kept for, all clients if not given
+    client: Option<ClientId>,
+    /// Transactions processed so far
+    seq: u64,
+    checkpoints: HashMap<ClientId, Vec<Checkpoint>>,
+    /// Processing index of every transaction with own tx
+    txs: HashMap<TxId, u64>,
+}
+
+impl Timeline {
+    /// Creates timeline keeping checkpoints only for given client, so its size doesn't grow with
+    /// activity of other clients. Transactions of all clients still have to be recorded, to know
+    /// their position in processing.
+    pub fn for_client(cid: ClientId) -> Self {
+        Self {
+            client: Some(cid),
+            ..Self::default()
+        }
+    }
+
+    /// Records transaction outcome, to be called after every processed transaction
+    pub fn record(&mut self, engine: &Engine, transaction: &Transaction, result: &Result<Outcome>) {
+        self.seq += 1;
+
+        // Duplicate doesn't change anything, and its tx points to the original transaction
+        if !matches!(result, Ok(Outcome::Applied)) {
+            return;
+        }
+
+        if let Transaction::Deposit { tx, .. }
+        | Transaction::Withdrawal { tx, .. }
+        | Transaction::Interest { tx, .. }
+        | Transaction::Convert { tx, .. } = transaction
+        {
+            self.txs.insert(*tx, self.seq);
+        }
+
+        let cid = transaction.cid();
+        if self.client.is_some_and(|client| client != cid) {
+            return;
+        }
+
+        if let Some(client) = engine.client_state(cid) {
+            self.checkpoints.entry(cid).or_default().push(Checkpoint {
+                seq: self.seq,
+                now: engine.now(),
+                client: client.clone(),
+            });
+        }
+    }
+
+    /// Returns client state at given point of processing
+    pub fn balance(&self, cid: ClientId, point: PointInTime) -> Result<Client> {
+        let checkpoints = match self.checkpoints.get(&cid) {
+            Some(checkpoints) => checkpoints.as_slice(),
+            None => &[],
+        };
+
+        let count = match point {
+            PointInTime::AfterTx(tx) => {
+                let seq = self
+                    .txs
+                    .get(&tx)
+                    .ok_or_else(|| anyhow!("No transaction performed with tx: {}", tx))?;
+                checkpoints.partition_point(|checkpoint| checkpoint.seq <= *seq)
+            }
+            PointInTime::At(timestamp) => checkpoints
+                .partition_point(|checkpoint| checkpoint.now.is_none_or(|now| now <= timestamp)),
+        };
+
+        Ok(match count {
+            0 => Client::new(cid),
+            count => checkpoints[count - 1].client.clone(),
+        })
+    }
+}
+
+/// Processes all transactions, building timeline of single client balances
+pub fn timeline(
+    records: impl IntoIterator<Item = Record>,
+    config: &Config,
+    cid: ClientId,
+) -> Timeline {
+    let mut timeline = Timeline::for_client(cid);
+    engine::process_with(records, config, |engine, record, result| {
+        timeline.record(engine, &record.transaction, result)
+    });
+    timeline
+}
+
+#[cfg(test)]
+mod test {
+    use super::{timeline, PointInTime};
+    use crate::client::Client;
+    use crate::config::Config;
+    use crate::decimal::Decimal;
+    use crate::transaction::{Record, Transaction};
+
+    fn record(transaction: Transaction, timestamp: u64) -> Record {
+        Record {
+            transaction,
+            timestamp: Some(timestamp),
+            position: None,
+            tenant: None,
+            currency: None,
+        }
+    }
+
+    #[test]
+    fn balances() {
+        let records = vec![
+            record(
+                Transaction::Deposit {
+                    cid: 1,
+                    tx: 1,
+                    amount: Decimal::new(10, 0),
+                },
+                100,
+            ),
+            record(
+                Transaction::Deposit {
+                    cid: 2,
+                    tx: 2,
+                    amount: Decimal::new(5, 0),
+                },
+                200,
+            ),
+            record(
+                Transaction::Dispute {
+                    cid: 1,
+                    tx: 1,
+                    amount: None,
+                },
+                300,
+            ),
+            record(
+                Transaction::Withdrawal {
+                    cid: 1,
+                    tx: 3,
+                    amount: Decimal::new(5, 0),
+                },
+                400,
+            ),
+            record(
+                Transaction::Resolve { cid: 1, tx: 1 },
+                // Out of order timestamp doesn't move time back
+                250,
+            ),
+        ];
+
+        let timeline = timeline(records, &Config::default(), 1);
+        let client = |available, held| Client {
+            cid: 1,
+            available: Decimal::new(available, 0),
+            held: Decimal::new(held, 0),
+            locked: false,
+        };
+
+        assert_eq!(
+            timeline.balance(1, PointInTime::AfterTx(1)).unwrap(),
+            client(10, 0)
+        );
+        assert_eq!(
+            timeline.balance(1, PointInTime::AfterTx(2)).unwrap(),
+            client(10, 0)
+        );
+        // Withdrawal was rejected
+        assert!(timeline.balance(1, PointInTime::AfterTx(3)).is_err());
+
+        assert_eq!(
+            timeline.balance(1, PointInTime::At(50)).unwrap(),
+            client(0, 0)
+        );
+        assert_eq!(
+            timeline.balance(1, PointInTime::At(399)).unwrap(),
+            client(0, 10)
+        );
+        assert_eq!(
+            timeline.balance(1, PointInTime::At(400)).unwrap(),
+            client(10, 0)
+        );
+        // Only the queried client is kept
+        assert_eq!(
+            timeline.balance(2, PointInTime::At(400)).unwrap(),
+            Client::new(2)
+        );
+        assert_eq!(timeline.checkpoints.len(), 1);
+    }
+}
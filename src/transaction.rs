@@ -1,52 +1,317 @@
 use crate::decimal::Decimal;
+use crate::rejection::{reject, Reason};
+use crate::schema::{InputSchema, Strictness};
 use crate::transaction_type::TransactionType;
 use anyhow::{anyhow, Error, Result};
 use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::Path;
+use std::rc::Rc;
+use tracing::warn;
+
+/// Point in time, as seconds since unix epoch
+pub type Timestamp = u64;
+
+/// Seconds in a day, for day-based policies
+pub const DAY: Timestamp = 24 * 60 * 60;
+
+/// Client identifier
+pub type ClientId = u64;
+
+/// Transaction identifier
+pub type TxId = u64;
+
+/// Currency of amount, `None` for the base currency
+pub type Currency = Option<Rc<str>>;
+
+/// Single transaction read from input, with its metadata
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub transaction: Transaction,
+    /// When transaction happened, if input provides it
+    pub timestamp: Option<Timestamp>,
+    /// Where in input transaction was read from, `None` if it didn't come from input
+    pub position: Option<Position>,
+    /// Tenant transaction belongs to, `None` for input without tenants
+    pub tenant: Option<Rc<str>>,
+    /// Currency of transaction amount
+    pub currency: Currency,
+}
+
+impl From<Transaction> for Record {
+    fn from(transaction: Transaction) -> Self {
+        Self {
+            transaction,
+            timestamp: None,
+            position: None,
+            tenant: None,
+            currency: None,
+        }
+    }
+}
+
+/// Place of the row in the input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Position {
+    /// Input file, `None` if input is not read from file
+    pub file: Option<Rc<Path>>,
+    /// Line of the row, starting from 1
+    pub line: u64,
+    /// Byte offset of the row start
+    pub byte: u64,
+}
+
+impl Position {
+    fn new(file: &Option<Rc<Path>>, position: &csv::Position) -> Self {
+        Self {
+            file: file.clone(),
+            line: position.line(),
+            byte: position.byte(),
+        }
+    }
+}
+
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file.display())?;
+        }
+        write!(f, "{} (byte {})", self.line, self.byte)
+    }
+}
+
+/// Row which couldn't be parsed, with its place in the input if it is known
+#[derive(Debug)]
+pub struct ParseError {
+    pub position: Option<Position>,
+    pub error: Error,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.position {
+            Some(position) => write!(f, "{}, at {}", self.error, position),
+            None => write!(f, "{}", self.error),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl ParseError {
+    /// Returns reason of rejecting the row, `Parse` if it is not more specific
+    pub fn reason(&self) -> Reason {
+        match Reason::of(&self.error) {
+            Reason::Other => Reason::Parse,
+            reason => reason,
+        }
+    }
+}
 
 /// Single transaction to be performed
-#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
-#[serde(try_from = "InputTransaction")]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Transaction {
-    Deposit { cid: u16, tx: u32, amount: Decimal },
-    Withdrawal { cid: u16, tx: u32, amount: Decimal },
-    Dispute { cid: u16, tx: u32 },
-    Resolve { cid: u16, tx: u32 },
-    Chargeback { cid: u16, tx: u32 },
+    Deposit {
+        cid: ClientId,
+        tx: TxId,
+        amount: Decimal,
+    },
+    Withdrawal {
+        cid: ClientId,
+        tx: TxId,
+        amount: Decimal,
+    },
+    /// Disputes whole deposit if amount is not given, or only given part of it
+    Dispute {
+        cid: ClientId,
+        tx: TxId,
+        amount: Option<Decimal>,
+    },
+    Resolve {
+        cid: ClientId,
+        tx: TxId,
+    },
+    Chargeback {
+        cid: ClientId,
+        tx: TxId,
+    },
+    /// Chargeback won back by merchant (re-presentment)
+    ChargebackReversal {
+        cid: ClientId,
+        tx: TxId,
+    },
+    /// Interest accrued on client funds, generated by engine
+    Interest {
+        cid: ClientId,
+        tx: TxId,
+        amount: Decimal,
+    },
+    /// Converts amount of client funds (in currency of record) into other currency
+    Convert {
+        cid: ClientId,
+        tx: TxId,
+        amount: Decimal,
+        to: Currency,
+    },
+}
+
+impl Transaction {
+    /// Returns id of client performing transaction
+    pub fn cid(&self) -> ClientId {
+        match self {
+            Self::Deposit { cid, .. }
+            | Self::Withdrawal { cid, .. }
+            | Self::Dispute { cid, .. }
+            | Self::Resolve { cid, .. }
+            | Self::Chargeback { cid, .. }
+            | Self::ChargebackReversal { cid, .. }
+            | Self::Interest { cid, .. }
+            | Self::Convert { cid, .. } => *cid,
+        }
+    }
+
+    /// Returns type of transaction
+    pub fn ttype(&self) -> TransactionType {
+        match self {
+            Self::Deposit { .. } => TransactionType::Deposit,
+            Self::Withdrawal { .. } => TransactionType::Withdrawal,
+            Self::Dispute { .. } => TransactionType::Dispute,
+            Self::Resolve { .. } => TransactionType::Resolve,
+            Self::Chargeback { .. } => TransactionType::Chargeback,
+            Self::ChargebackReversal { .. } => TransactionType::ChargebackReversal,
+            Self::Interest { .. } => TransactionType::Interest,
+            Self::Convert { .. } => TransactionType::Convert,
+        }
+    }
+
+    /// Returns amount given with transaction, if any
+    pub fn amount(&self) -> Option<Decimal> {
+        match self {
+            Self::Deposit { amount, .. }
+            | Self::Withdrawal { amount, .. }
+            | Self::Interest { amount, .. }
+            | Self::Convert { amount, .. } => Some(*amount),
+            Self::Dispute { amount, .. } => *amount,
+            _ => None,
+        }
+    }
+
+    /// Returns id of transaction, or of transaction it refers to
+    pub fn tx(&self) -> TxId {
+        match self {
+            Self::Deposit { tx, .. }
+            | Self::Withdrawal { tx, .. }
+            | Self::Dispute { tx, .. }
+            | Self::Resolve { tx, .. }
+            | Self::Chargeback { tx, .. }
+            | Self::ChargebackReversal { tx, .. }
+            | Self::Interest { tx, .. }
+            | Self::Convert { tx, .. } => *tx,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct InputTransaction {
+    // Parsed separately, as input schema may give other names of types
     #[serde(rename = "type")]
-    ttype: TransactionType,
+    ttype: String,
     #[serde(rename = "client")]
-    cid: u16,
-    tx: u32,
-    // Amount might be messing for some transactions
+    cid: ClientId,
+    tx: TxId,
+    // Amount might be messing for some transactions, for dispute it is optional part of disputed
+    // transaction
     amount: Option<Decimal>,
+    // Timestamp column is optional as a whole
+    #[serde(default)]
+    timestamp: Option<Timestamp>,
+    // So is tenant column
+    #[serde(default)]
+    tenant: Option<String>,
+    // And currency columns - empty currency is the base one
+    #[serde(default)]
+    currency: Option<String>,
+    #[serde(default)]
+    to_currency: Option<String>,
 }
 
-impl std::convert::TryFrom<InputTransaction> for Transaction {
-    type Error = Error;
+impl InputTransaction {
+    /// Verifies amount is not given for transaction which doesn't use it, as schema requires
+    fn check_unexpected_amount(
+        &self,
+        ttype: TransactionType,
+        strictness: Strictness,
+        position: Option<&Position>,
+    ) -> Result<()> {
+        let unexpected = self.amount.is_some()
+            && matches!(
+                ttype,
+                TransactionType::Resolve
+                    | TransactionType::Chargeback
+                    | TransactionType::ChargebackReversal
+            );
 
-    fn try_from(
-        InputTransaction {
-            ttype,
+        match strictness {
+            Strictness::Reject if unexpected => Err(reject!(
+                Reason::UnexpectedAmount,
+                "Amount given for {} transaction, tx: {}",
+                ttype.as_str(),
+                self.tx
+            )),
+            Strictness::Warn if unexpected => {
+                warn!(
+                    tx = self.tx,
+                    client = self.cid,
+                    r#type = ttype.as_str(),
+                    line = position.map(|position| position.line),
+                    byte = position.map(|position| position.byte),
+                    reason = Reason::UnexpectedAmount.as_str(),
+                    "Ignoring amount of transaction"
+                );
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Builds record of given type, ignoring the one read. Target currency of conversion is given
+    /// too, as currency names are shared between records.
+    fn into_record(self, ttype: TransactionType, to: Currency) -> Result<Record> {
+        let Self {
             cid,
             tx,
             amount,
-        }: InputTransaction,
-    ) -> Result<Self> {
-        let result = match ttype {
+            timestamp,
+            ..
+        } = self;
+
+        if let (
+            TransactionType::Deposit | TransactionType::Withdrawal | TransactionType::Convert,
+            Some(amount),
+        ) = (ttype, amount)
+        {
+            if amount <= Decimal::new(0, 0) {
+                return Err(reject!(
+                    Reason::InvalidAmount,
+                    "Amount of {} has to be positive, tx: {}, amount: {}",
+                    ttype.as_str(),
+                    tx,
+                    amount
+                ));
+            }
+        }
+
+        let transaction = match ttype {
             TransactionType::Deposit => {
                 if let Some(amount) = amount {
-                    Self::Deposit { cid, tx, amount }
+                    Transaction::Deposit { cid, tx, amount }
                 } else {
                     return Err(anyhow!("Missing amount on deposit transaction, tx: {}", tx));
                 }
             }
             TransactionType::Withdrawal => {
                 if let Some(amount) = amount {
-                    Self::Withdrawal { cid, tx, amount }
+                    Transaction::Withdrawal { cid, tx, amount }
                 } else {
                     return Err(anyhow!(
                         "Missing amount on withdrawal transaction, tx: {}",
@@ -54,28 +319,180 @@ impl std::convert::TryFrom<InputTransaction> for Transaction {
                     ));
                 }
             }
-            TransactionType::Dispute => Self::Dispute { cid, tx },
-            TransactionType::Resolve => Self::Resolve { cid, tx },
-            TransactionType::Chargeback => Self::Chargeback { cid, tx },
+            TransactionType::Dispute => Transaction::Dispute { cid, tx, amount },
+            TransactionType::Resolve => Transaction::Resolve { cid, tx },
+            TransactionType::Chargeback => Transaction::Chargeback { cid, tx },
+            TransactionType::ChargebackReversal => Transaction::ChargebackReversal { cid, tx },
+            TransactionType::Interest => {
+                return Err(anyhow!(
+                    "Interest transactions are generated by engine, they cannot be given in input, tx: {}",
+                    tx
+                ))
+            }
+            TransactionType::Convert => {
+                if let Some(amount) = amount {
+                    Transaction::Convert {
+                        cid,
+                        tx,
+                        amount,
+                        to,
+                    }
+                } else {
+                    return Err(anyhow!("Missing amount on convert transaction, tx: {}", tx));
+                }
+            }
         };
 
-        Ok(result)
+        Ok(Record {
+            transaction,
+            timestamp,
+            position: None,
+            tenant: None,
+            currency: None,
+        })
+    }
+}
+
+/// Transactions read from input, with knowledge about its columns
+pub struct Transactions<I> {
+    records: I,
+    tenanted: bool,
+    multi_currency: bool,
+}
+
+impl<I> Transactions<I> {
+    /// Whether input has `tenant` column - even if all of its values are empty
+    pub fn has_tenant_column(&self) -> bool {
+        self.tenanted
+    }
+
+    /// Whether input has `currency` or `to_currency` column - even if all of its values are empty,
+    /// so balances in other currencies can be stored
+    pub fn has_currency_column(&self) -> bool {
+        self.multi_currency
     }
 }
 
-/// Reads transaction from given reader
-pub fn read_transactions(reader: impl std::io::Read) -> impl Iterator<Item = Result<Transaction>> {
-    csv::ReaderBuilder::new()
-        .trim(csv::Trim::All)
-        .from_reader(reader)
-        .into_deserialize()
-        .map(|item| item.map_err(Into::into))
+impl<I: Iterator> Iterator for Transactions<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.records.next()
+    }
+}
+
+/// Reads transaction from given reader, in the default format
+pub fn read_transactions(
+    reader: impl std::io::Read,
+) -> Transactions<impl Iterator<Item = Result<Record, ParseError>>> {
+    read_source(reader, None, &InputSchema::default()).expect("Default input schema is valid")
+}
+
+/// Reads transactions from given reader, in given format
+pub fn read_transactions_with(
+    reader: impl std::io::Read,
+    schema: &InputSchema,
+) -> Result<Transactions<impl Iterator<Item = Result<Record, ParseError>>>> {
+    read_source(reader, None, schema)
+}
+
+/// Reads transactions from file, so their positions point to it
+pub fn read_transactions_file(
+    path: &Path,
+    schema: &InputSchema,
+) -> Result<Transactions<impl Iterator<Item = Result<Record, ParseError>>>> {
+    let file = std::fs::File::open(path)?;
+    read_source(file, Some(path.into()), schema)
+}
+
+/// Fails only if schema is invalid, or header doesn't match it
+fn read_source(
+    reader: impl std::io::Read,
+    file: Option<Rc<Path>>,
+    schema: &InputSchema,
+) -> Result<Transactions<impl Iterator<Item = Result<Record, ParseError>>>> {
+    let mut reader = schema.reader_builder()?.from_reader(reader);
+    let headers = if schema.has_headers {
+        // If headers are broken, every record fails to deserialize anyway
+        match reader.headers() {
+            Ok(headers) => Some(schema.headers(headers)?),
+            Err(_) => None,
+        }
+    } else {
+        Some(schema.headers(schema.columns.iter().map(String::as_str))?)
+    };
+
+    let has_column = |name: &str| {
+        headers
+            .as_ref()
+            .is_some_and(|headers| headers.iter().any(|column| column == name))
+    };
+    let tenanted = has_column("tenant");
+    let multi_currency = has_column("currency") || has_column("to_currency");
+
+    // Iterator outlives the borrow, and types are parsed with every record
+    let schema = schema.clone();
+    // Every tenant and currency name is allocated once, and shared by its records
+    let mut names = HashSet::<Rc<str>>::new();
+    let mut name = move |name: String| match names.get(name.as_str()) {
+        Some(name) => name.clone(),
+        None => {
+            let name: Rc<str> = name.into();
+            names.insert(name.clone());
+            name
+        }
+    };
+
+    let records = reader.into_records().map(move |record| {
+        let record = record.map_err(|err| ParseError {
+            position: err
+                .position()
+                .map(|position| Position::new(&file, position)),
+            error: err.into(),
+        })?;
+        let position = record
+            .position()
+            .map(|position| Position::new(&file, position));
+
+        let parsed = record
+            .deserialize::<InputTransaction>(headers.as_ref())
+            .map_err(Error::from)
+            .and_then(|mut input| {
+                let ttype = schema.transaction_type(&input.ttype)?;
+                input.check_unexpected_amount(
+                    ttype,
+                    schema.unexpected_amount,
+                    position.as_ref(),
+                )?;
+                let tenant = input.tenant.take().map(&mut name);
+                let currency = input.currency.take().map(&mut name);
+                let to = input.to_currency.take().map(&mut name);
+                Ok(Record {
+                    tenant,
+                    currency,
+                    ..input.into_record(ttype, to)?
+                })
+            });
+
+        match parsed {
+            Ok(parsed) => Ok(Record { position, ..parsed }),
+            Err(error) => Err(ParseError { position, error }),
+        }
+    });
+
+    Ok(Transactions {
+        records,
+        tenanted,
+        multi_currency,
+    })
 }
 
 #[cfg(test)]
 mod test {
-    use super::{read_transactions, Transaction};
+    use super::{read_transactions, read_transactions_with, Position, Record, Transaction};
     use crate::decimal::Decimal;
+    use crate::rejection::Reason;
+    use crate::schema::{InputSchema, Strictness};
 
     #[test]
     fn reading() {
@@ -86,11 +503,12 @@ withdrawal, 1, 4, 1.5
 dispute, 1, 5,
 dispute, 1, 6,3.0
 resolve, 1, 5,
-chargeback, 1, 6,"#;
+chargeback, 1, 6,
+chargeback_reversal, 1, 6,"#;
 
         assert_eq!(
             read_transactions(&data[..])
-                .map(Result::unwrap)
+                .map(|record| record.unwrap().transaction)
                 .collect::<Vec<_>>(),
             vec![
                 Transaction::Deposit {
@@ -103,11 +521,254 @@ chargeback, 1, 6,"#;
                     tx: 4,
                     amount: Decimal::new(1, 5000),
                 },
-                Transaction::Dispute { cid: 1, tx: 5 },
-                Transaction::Dispute { cid: 1, tx: 6 },
+                Transaction::Dispute {
+                    cid: 1,
+                    tx: 5,
+                    amount: None,
+                },
+                Transaction::Dispute {
+                    cid: 1,
+                    tx: 6,
+                    amount: Some(Decimal::new(3, 0)),
+                },
                 Transaction::Resolve { cid: 1, tx: 5 },
                 Transaction::Chargeback { cid: 1, tx: 6 },
+                Transaction::ChargebackReversal { cid: 1, tx: 6 },
+            ]
+        );
+    }
+
+    #[test]
+    fn reading_timestamps() {
+        let data = br#"
+type, client, tx, amount, timestamp
+deposit, 1, 1, 1.0, 1600000000
+dispute, 1, 1,,"#;
+
+        assert_eq!(
+            read_transactions(&data[..])
+                .map(Result::unwrap)
+                .collect::<Vec<_>>(),
+            vec![
+                Record {
+                    transaction: Transaction::Deposit {
+                        cid: 1,
+                        tx: 1,
+                        amount: Decimal::new(1, 0),
+                    },
+                    timestamp: Some(1_600_000_000),
+                    position: Some(Position {
+                        file: None,
+                        line: 3,
+                        byte: 37,
+                    }),
+                    tenant: None,
+                    currency: None,
+                },
+                Record {
+                    transaction: Transaction::Dispute {
+                        cid: 1,
+                        tx: 1,
+                        amount: None,
+                    },
+                    timestamp: None,
+                    position: Some(Position {
+                        file: None,
+                        line: 4,
+                        byte: 68,
+                    }),
+                    tenant: None,
+                    currency: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_error_positions() {
+        let data = br#"type, client, tx, amount
+deposit, 1, 1, 1.0
+deposit, 1, x, 1.0
+withdrawal, 1, 2
+deposit, 1, 3,"#;
+
+        let errors: Vec<_> = read_transactions(&data[..])
+            .filter_map(Result::err)
+            .map(|err| err.to_string())
+            .collect();
+        assert_eq!(errors.len(), 3);
+        assert!(errors[0].ends_with(", at 3 (byte 44)"), "{}", errors[0]);
+        assert!(errors[1].ends_with(", at 4 (byte 63)"), "{}", errors[1]);
+        assert!(errors[2].ends_with(", at 5 (byte 80)"), "{}", errors[2]);
+    }
+
+    #[test]
+    fn reading_with_schema() {
+        let expected = vec![
+            Transaction::Deposit {
+                cid: 1,
+                tx: 1,
+                amount: Decimal::new(1, 5000),
+            },
+            Transaction::Resolve { cid: 2, tx: 3 },
+        ];
+        let read = |data: &[u8], schema| {
+            read_transactions_with(data, &schema)
+                .unwrap()
+                .map(|record| record.unwrap().transaction)
+                .collect::<Vec<_>>()
+        };
+
+        let schema = InputSchema {
+            delimiter: ';',
+            has_headers: false,
+            columns: vec!["client".into(), "tx".into(), "type".into(), "amount".into()],
+            ..InputSchema::default()
+        };
+        assert_eq!(
+            read(b"1; 1; deposit; 1.5\n2; 3; resolve;", schema),
+            expected
+        );
+
+        let schema = InputSchema {
+            aliases: vec![
+                ("client_id".into(), "client".into()),
+                ("transaction_id".into(), "tx".into()),
+            ]
+            .into_iter()
+            .collect(),
+            ..InputSchema::default()
+        };
+        let data = br#"transaction_id, note, type, amount, client_id
+1,"first, deposit",deposit,1.5,1
+3,, resolve,, 2"#;
+        assert_eq!(read(data, schema.clone()), expected);
+
+        let schema = InputSchema {
+            ignore_unknown_columns: false,
+            ..schema
+        };
+        assert!(read_transactions_with(&data[..], &schema).is_err());
+    }
+
+    #[test]
+    fn amount_validation() {
+        let data = br#"type, client, tx, amount
+deposit, 1, 1, 0.0
+withdrawal, 1, 2, -1.0
+dispute, 1, 3, 1.0
+resolve, 1, 3, 1.0
+chargeback, 1, 3,"#;
+        let reasons = |schema| {
+            read_transactions_with(&data[..], &schema)
+                .unwrap()
+                .map(|record| {
+                    record
+                        .map(|_| None)
+                        .unwrap_or_else(|err| Some(err.reason()))
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let expected = vec![
+            Some(Reason::InvalidAmount),
+            Some(Reason::InvalidAmount),
+            None,
+            None,
+            None,
+        ];
+        assert_eq!(reasons(InputSchema::default()), expected);
+        let schema = InputSchema {
+            unexpected_amount: Strictness::Warn,
+            ..InputSchema::default()
+        };
+        assert_eq!(reasons(schema), expected);
+
+        let schema = InputSchema {
+            unexpected_amount: Strictness::Reject,
+            ..InputSchema::default()
+        };
+        assert_eq!(
+            reasons(schema),
+            vec![
+                Some(Reason::InvalidAmount),
+                Some(Reason::InvalidAmount),
+                None,
+                Some(Reason::UnexpectedAmount),
+                None,
             ]
         );
     }
+
+    #[test]
+    fn reading_wide_ids() {
+        let data = br#"type, client, tx, amount
+deposit, 70000, 5000000000, 1.0"#;
+
+        assert_eq!(
+            read_transactions(&data[..])
+                .map(|record| record.unwrap().transaction)
+                .collect::<Vec<_>>(),
+            vec![Transaction::Deposit {
+                cid: 70_000,
+                tx: 5_000_000_000,
+                amount: Decimal::new(1, 0),
+            }]
+        );
+    }
+
+    #[test]
+    fn reading_tenants() {
+        let data = br#"type, client, tx, amount, tenant
+deposit, 1, 1, 1.0,
+deposit, 1, 2, 1.0, brand"#;
+
+        let transactions = read_transactions(&data[..]);
+        assert!(transactions.has_tenant_column());
+        assert_eq!(
+            transactions
+                .map(|record| record.unwrap().tenant)
+                .collect::<Vec<_>>(),
+            vec![None, Some("brand".into())]
+        );
+
+        // Column decides, even if it has no values
+        let data = br#"type, client, tx, amount, tenant
+deposit, 1, 1, 1.0,"#;
+        assert!(read_transactions(&data[..]).has_tenant_column());
+
+        let data = br#"type, client, tx, amount
+deposit, 1, 1, 1.0"#;
+        assert!(!read_transactions(&data[..]).has_tenant_column());
+    }
+
+    #[test]
+    fn reading_currencies() {
+        let data = br#"type, client, tx, amount, currency, to_currency
+deposit, 1, 1, 1.0, ,
+convert, 1, 2, 1.0, , EUR
+withdrawal, 1, 3, 0.5, EUR,
+convert, 1, 4, , EUR, USD"#;
+
+        let transactions = read_transactions(&data[..]);
+        assert!(transactions.has_currency_column());
+        let records: Vec<_> = transactions.collect();
+        assert_eq!(records[0].as_ref().unwrap().currency, None);
+        assert_eq!(
+            records[1].as_ref().unwrap().transaction,
+            Transaction::Convert {
+                cid: 1,
+                tx: 2,
+                amount: Decimal::new(1, 0),
+                to: Some("EUR".into()),
+            }
+        );
+        assert_eq!(records[2].as_ref().unwrap().currency, Some("EUR".into()));
+        // Conversion needs amount
+        assert!(records[3].is_err());
+
+        let data = br#"type, client, tx, amount
+deposit, 1, 1, 1.0"#;
+        assert!(!read_transactions(&data[..]).has_currency_column());
+    }
 }
@@ -1,7 +1,8 @@
-use serde::Deserialize;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
 
 /// Transaction type
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum TransactionType {
     Deposit,
@@ -9,4 +10,70 @@ pub enum TransactionType {
     Dispute,
     Resolve,
     Chargeback,
+    #[serde(rename = "chargeback_reversal")]
+    ChargebackReversal,
+    /// Generated by engine, never read from input
+    Interest,
+    Convert,
+}
+
+impl TransactionType {
+    const ALL: [Self; 8] = [
+        Self::Deposit,
+        Self::Withdrawal,
+        Self::Dispute,
+        Self::Resolve,
+        Self::Chargeback,
+        Self::ChargebackReversal,
+        Self::Interest,
+        Self::Convert,
+    ];
+
+    /// Returns name of the type, as used in input
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Deposit => "deposit",
+            Self::Withdrawal => "withdrawal",
+            Self::Dispute => "dispute",
+            Self::Resolve => "resolve",
+            Self::Chargeback => "chargeback",
+            Self::ChargebackReversal => "chargeback_reversal",
+            Self::Interest => "interest",
+            Self::Convert => "convert",
+        }
+    }
+}
+
+/// Parses type by its name, ignoring case
+impl std::str::FromStr for TransactionType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|ttype| ttype.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| anyhow!("Unknown transaction type: {}", s))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TransactionType;
+
+    #[test]
+    fn parse() {
+        for ttype in TransactionType::ALL.iter() {
+            assert_eq!(ttype.as_str().parse::<TransactionType>().unwrap(), *ttype);
+        }
+        assert_eq!(
+            "DEPOSIT".parse::<TransactionType>().unwrap(),
+            TransactionType::Deposit
+        );
+        assert_eq!(
+            "Chargeback_Reversal".parse::<TransactionType>().unwrap(),
+            TransactionType::ChargebackReversal
+        );
+        assert!("withdraw".parse::<TransactionType>().is_err());
+    }
 }